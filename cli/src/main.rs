@@ -1,5 +1,5 @@
 use clap::{App, Arg};
-use gb::{Color, Gameboy, Joypad, ROM};
+use gb::{Gameboy, Joypad, ROM};
 use std::{cell::RefCell, rc::Rc};
 
 struct HAL;
@@ -9,8 +9,6 @@ impl gb::HAL for HAL {
         false
     }
 
-    fn put_pixel(&mut self, _: usize, _: usize, _: Color) {}
-
     fn serial_callback(&mut self, value: u8) -> u8 {
         print!("{}", value as char);
 
@@ -35,10 +33,16 @@ fn main() {
         .get_matches();
 
     let bytes = std::fs::read(matches.value_of("INPUT").unwrap()).unwrap();
-    let rom = ROM::from(bytes);
+    let rom = match ROM::parse(bytes) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed to load ROM: {}", e);
+            std::process::exit(1);
+        }
+    };
     let hal = Rc::new(RefCell::new(HAL));
 
-    let mut gameboy = Gameboy::new(rom, hal);
+    let mut gameboy = Gameboy::new(rom, hal).unwrap();
 
     loop {
         gameboy.step();
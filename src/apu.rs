@@ -0,0 +1,678 @@
+mod blip;
+mod envelope;
+mod high_pass;
+mod length;
+mod noise;
+mod pulse;
+mod wave;
+
+use blip::BlipBuffer;
+use high_pass::HighPass;
+use noise::Noise;
+use pulse::Pulse;
+use wave::Wave;
+
+use alloc::vec::Vec;
+use bitfield::Bit;
+
+use crate::audio_buffer::{AudioBuffer, DEFAULT_SAMPLE_RATE};
+use crate::audio_capture::AudioCapture;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::Model;
+
+// The output is sampled once per M-cycle
+const CLOCK_RATE: u32 = 1_048_576;
+
+/// How much charge the capacitor on the output keeps each T-cycle.
+fn capacitor_charge(model: Model) -> f64 {
+    match model {
+        Model::MGB | Model::CGB | Model::AGB => 0.998943,
+        _ => 0.999958,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Channel {
+    Pulse1 = 0,
+    Pulse2 = 1,
+    Wave = 2,
+    Noise = 3,
+}
+
+/// Resamples each channel on its own while they are being captured.
+struct ChannelResampler {
+    blips: [BlipBuffer; 4],
+    high_pass: [HighPass; 4],
+}
+
+impl ChannelResampler {
+    fn new(capacitor_charge: f64, sample_rate: u32) -> Self {
+        let blip = || BlipBuffer::new(CLOCK_RATE, sample_rate);
+        let high_pass = || HighPass::new(capacitor_charge, sample_rate);
+
+        ChannelResampler {
+            blips: [blip(), blip(), blip(), blip()],
+            high_pass: [high_pass(), high_pass(), high_pass(), high_pass()],
+        }
+    }
+}
+
+/// The four sound channels at 0xFF10-0xFF3F, clocked by the frame sequencer
+/// which runs at 512 Hz off a bit of the timer's divider.
+pub struct APU {
+    enabled: bool,
+    pulse1: Pulse,
+    pulse2: Pulse,
+    wave: Wave,
+    noise: Noise,
+    nr50: u8,
+    nr51: u8,
+
+    // The next step of the frame sequencer and the divider bit that clocks it
+    frame_step: u8,
+    div_bit: bool,
+
+    // The output resampled for the frontend, a frame at a time
+    left: BlipBuffer,
+    right: BlipBuffer,
+    high_pass: [HighPass; 2],
+    capacitor_charge: f64,
+    audio_buffer: AudioBuffer,
+
+    muted: [bool; 4],
+    soloed: [bool; 4],
+    capture: Option<AudioCapture>,
+    channel_resampler: Option<ChannelResampler>,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            enabled: false,
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            nr50: 0x00,
+            nr51: 0x00,
+
+            frame_step: 0,
+            div_bit: false,
+
+            left: BlipBuffer::new(CLOCK_RATE, DEFAULT_SAMPLE_RATE),
+            right: BlipBuffer::new(CLOCK_RATE, DEFAULT_SAMPLE_RATE),
+            high_pass: [
+                HighPass::new(capacitor_charge(Model::DMG), DEFAULT_SAMPLE_RATE),
+                HighPass::new(capacitor_charge(Model::DMG), DEFAULT_SAMPLE_RATE),
+            ],
+            capacitor_charge: capacitor_charge(Model::DMG),
+            audio_buffer: AudioBuffer::new(DEFAULT_SAMPLE_RATE),
+
+            muted: [false; 4],
+            soloed: [false; 4],
+            capture: None,
+            channel_resampler: None,
+        }
+    }
+
+    /// The state the boot ROM leaves the APU in after playing the startup
+    /// sound on the first pulse channel.
+    pub fn post_boot(model: Model) -> Self {
+        let mut apu = APU::new();
+
+        apu.capacitor_charge = capacitor_charge(model);
+        apu.high_pass = [
+            HighPass::new(apu.capacitor_charge, DEFAULT_SAMPLE_RATE),
+            HighPass::new(apu.capacitor_charge, DEFAULT_SAMPLE_RATE),
+        ];
+
+        apu.write(0xFF26, 0x80);
+        apu.write(0xFF11, 0xBF);
+        apu.write(0xFF12, 0xF3);
+        apu.write(0xFF13, 0xC1);
+        apu.write(0xFF14, 0x07);
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0xF3);
+
+        // The Super Game Boy boot ROM doesn't play the sound
+        apu.pulse1
+            .set_enabled(!matches!(model, Model::SGB | Model::SGB2));
+
+        apu
+    }
+
+    fn nr52(&self) -> u8 {
+        (self.enabled as u8) << 7
+            | 0x70
+            | (self.noise.enabled() as u8) << 3
+            | (self.wave.enabled() as u8) << 2
+            | (self.pulse2.enabled() as u8) << 1
+            | self.pulse1.enabled() as u8
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF10..=0xFF14 => self.pulse1.read(addr - 0xFF10),
+            0xFF16..=0xFF19 => self.pulse2.read(addr - 0xFF15),
+            0xFF1A..=0xFF1E => self.wave.read(addr - 0xFF1A),
+            0xFF20..=0xFF23 => self.noise.read(addr - 0xFF1F),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            0xFF26 => self.nr52(),
+            0xFF30..=0xFF3F => self.wave.read_ram(addr - 0xFF30),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        // Only the lengths, NR52 and wave RAM can be written while it's off
+        if !self.enabled {
+            match addr {
+                0xFF11 => self.pulse1.write_length(value),
+                0xFF16 => self.pulse2.write_length(value),
+                0xFF1B => self.wave.write_length(value),
+                0xFF20 => self.noise.write_length(value),
+                0xFF26 => self.set_nr52(value),
+                0xFF30..=0xFF3F => self.wave.write_ram(addr - 0xFF30, value),
+                _ => {}
+            }
+
+            return;
+        }
+
+        // Enabling the length in the first half of a length period clocks it
+        let extra_length_clock = !self.frame_step.is_multiple_of(2);
+
+        match addr {
+            0xFF10..=0xFF14 => self.pulse1.write(addr - 0xFF10, value, extra_length_clock),
+            0xFF16..=0xFF19 => self.pulse2.write(addr - 0xFF15, value, extra_length_clock),
+            0xFF1A..=0xFF1E => self.wave.write(addr - 0xFF1A, value, extra_length_clock),
+            0xFF20..=0xFF23 => self.noise.write(addr - 0xFF1F, value, extra_length_clock),
+            0xFF24 => self.nr50 = value,
+            0xFF25 => self.nr51 = value,
+            0xFF26 => self.set_nr52(value),
+            0xFF30..=0xFF3F => self.wave.write_ram(addr - 0xFF30, value),
+            _ => {}
+        }
+    }
+
+    fn set_nr52(&mut self, value: u8) {
+        let enabled = value.bit(7);
+
+        if self.enabled && !enabled {
+            self.pulse1.power_off();
+            self.pulse2.power_off();
+            self.wave.power_off();
+            self.noise.power_off();
+            self.nr50 = 0x00;
+            self.nr51 = 0x00;
+        } else if !self.enabled && enabled {
+            self.frame_step = 0;
+        }
+
+        self.enabled = enabled;
+    }
+
+    /// The values of 0xFF10-0xFF3F, with the frequencies that can't be read
+    /// back filled in.
+    pub fn registers(&self) -> [u8; 0x30] {
+        let mut registers = [0xFF; 0x30];
+
+        for (offset, register) in registers.iter_mut().enumerate() {
+            *register = self.read(0xFF10 + offset as u16);
+        }
+
+        let [low, high] = self.pulse1.frequency().to_le_bytes();
+        registers[0x03] = low;
+        registers[0x04] = registers[0x04] & 0xF8 | high;
+
+        let [low, high] = self.pulse2.frequency().to_le_bytes();
+        registers[0x08] = low;
+        registers[0x09] = registers[0x09] & 0xF8 | high;
+
+        let [low, high] = self.wave.frequency().to_le_bytes();
+        registers[0x0D] = low;
+        registers[0x0E] = registers[0x0E] & 0xF8 | high;
+
+        registers
+    }
+
+    /// Restores 0xFF10-0xFF3F without triggering any of the channels.
+    pub fn restore_registers(&mut self, registers: &[u8]) {
+        let wave_enabled = self.wave.enabled();
+        self.wave.set_enabled(false);
+
+        for (offset, &value) in registers[0x20..0x30].iter().enumerate() {
+            self.wave.write_ram(offset as u16, value);
+        }
+
+        self.wave.set_enabled(wave_enabled);
+
+        let nr52 = registers[0x16];
+        self.set_nr52(nr52);
+
+        for (offset, &value) in registers[..0x16].iter().enumerate() {
+            let addr = 0xFF10 + offset as u16;
+
+            match addr {
+                0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => self.write(addr, value & 0x7F),
+                _ => self.write(addr, value),
+            }
+        }
+
+        self.pulse1.set_enabled(nr52.bit(0));
+        self.pulse2.set_enabled(nr52.bit(1));
+        self.wave.set_enabled(nr52.bit(2));
+        self.noise.set_enabled(nr52.bit(3));
+    }
+
+    /// Runs for an M-cycle. The frame sequencer is clocked on the falling edge
+    /// of `div_bit`.
+    pub fn tick_m_cycle(&mut self, div_bit: bool) {
+        let falling_edge = self.div_bit && !div_bit;
+        self.div_bit = div_bit;
+
+        if self.enabled {
+            if falling_edge {
+                self.clock_frame_sequencer();
+            }
+
+            for _ in 0..4 {
+                self.pulse1.tick();
+                self.pulse2.tick();
+                self.wave.tick();
+                self.noise.tick();
+            }
+        }
+
+        let channels = self.channel_outputs();
+        let (left, right) = self.mix(&channels);
+
+        self.left.set_amplitude(left);
+        self.right.set_amplitude(right);
+        self.left.clock();
+        self.right.clock();
+
+        if let Some(resampler) = &mut self.channel_resampler {
+            for (blip, &output) in resampler.blips.iter_mut().zip(channels.iter()) {
+                blip.set_amplitude(output);
+                blip.clock();
+            }
+        }
+    }
+
+    /// The samples made during the last frame.
+    pub fn audio_buffer(&self) -> &AudioBuffer {
+        &self.audio_buffer
+    }
+
+    /// Moves the samples made since the last frame into the audio buffer.
+    pub fn end_frame(&mut self) {
+        let mut left = Vec::with_capacity(self.left.samples_available());
+        self.left.read_samples(|sample| left.push(sample));
+
+        let mut left = left.into_iter();
+        let audio_buffer = &mut self.audio_buffer;
+        let [left_high_pass, right_high_pass] = &mut self.high_pass;
+
+        audio_buffer.clear();
+
+        self.right.read_samples(|right| {
+            let left = left.next().unwrap_or(0.0);
+
+            audio_buffer.push(left_high_pass.filter(left), right_high_pass.filter(right));
+        });
+
+        if let Some(capture) = &mut self.capture {
+            capture.push_mix(self.audio_buffer.samples());
+
+            if let Some(resampler) = &mut self.channel_resampler {
+                let ChannelResampler { blips, high_pass } = resampler;
+
+                for (channel, (blip, high_pass)) in
+                    blips.iter_mut().zip(high_pass.iter_mut()).enumerate()
+                {
+                    blip.read_samples(|sample| {
+                        capture.push_channel(channel, high_pass.filter(sample))
+                    });
+                }
+            }
+        }
+    }
+
+    /// Starts recording the output from the next frame on, and each channel
+    /// on its own if `channels` is set. Any capture already running is
+    /// thrown away.
+    pub fn start_capture(&mut self, channels: bool) {
+        let sample_rate = self.audio_buffer.sample_rate();
+
+        self.capture = Some(AudioCapture::new(sample_rate, channels));
+        self.channel_resampler = if channels {
+            Some(ChannelResampler::new(self.capacitor_charge, sample_rate))
+        } else {
+            None
+        };
+    }
+
+    pub fn stop_capture(&mut self) -> Option<AudioCapture> {
+        self.channel_resampler = None;
+        self.capture.take()
+    }
+
+    pub fn capture(&self) -> Option<&AudioCapture> {
+        self.capture.as_ref()
+    }
+
+    /// Takes a channel out of the mix.
+    pub fn set_muted(&mut self, channel: Channel, muted: bool) {
+        self.muted[channel as usize] = muted;
+    }
+
+    /// While any channels are soloed, only they are mixed.
+    pub fn set_soloed(&mut self, channel: Channel, soloed: bool) {
+        self.soloed[channel as usize] = soloed;
+    }
+
+    fn audible(&self, channel: usize) -> bool {
+        let soloing = self.soloed.iter().any(|&soloed| soloed);
+
+        !self.muted[channel] && (!soloing || self.soloed[channel])
+    }
+
+    /// Changing the rate stops any capture, since it can only hold one rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.stop_capture();
+
+        self.left.set_rates(CLOCK_RATE, sample_rate);
+        self.right.set_rates(CLOCK_RATE, sample_rate);
+
+        for high_pass in &mut self.high_pass {
+            high_pass.set_sample_rate(sample_rate);
+        }
+
+        self.audio_buffer.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_high_pass_filter(&mut self, enabled: bool) {
+        for high_pass in &mut self.high_pass {
+            high_pass.set_enabled(enabled);
+        }
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        if self.frame_step.is_multiple_of(2) {
+            self.pulse1.clock_length();
+            self.pulse2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.pulse1.clock_sweep();
+        }
+
+        if self.frame_step == 7 {
+            self.pulse1.clock_envelope();
+            self.pulse2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    /// The output of each channel's DAC from -1.0 to 1.0, or 0.0 when the DAC
+    /// is off.
+    pub fn channel_outputs(&self) -> [f32; 4] {
+        let dac = |enabled: bool, output: u8| {
+            if enabled {
+                f32::from(output) / 7.5 - 1.0
+            } else {
+                0.0
+            }
+        };
+
+        [
+            dac(self.pulse1.dac_enabled(), self.pulse1.output()),
+            dac(self.pulse2.dac_enabled(), self.pulse2.output()),
+            dac(self.wave.dac_enabled(), self.wave.output()),
+            dac(self.noise.dac_enabled(), self.noise.output()),
+        ]
+    }
+
+    /// The left and right outputs from -1.0 to 1.0, after panning with NR51
+    /// and the master volume in NR50.
+    pub fn output(&self) -> (f32, f32) {
+        self.mix(&self.channel_outputs())
+    }
+
+    fn mix(&self, channels: &[f32; 4]) -> (f32, f32) {
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        for (channel, output) in channels.iter().enumerate() {
+            if !self.audible(channel) {
+                continue;
+            }
+
+            if self.nr51.bit(channel + 4) {
+                left += output;
+            }
+
+            if self.nr51.bit(channel) {
+                right += output;
+            }
+        }
+
+        let left_volume = f32::from((self.nr50 >> 4 & 0x07) + 1) / 8.0;
+        let right_volume = f32::from((self.nr50 & 0x07) + 1) / 8.0;
+
+        (left * left_volume / 4.0, right * right_volume / 4.0)
+    }
+
+    /// Puts the channels and registers back to how they are at power on,
+    /// keeping the output settings such as the sample rate, mute and solo
+    /// and any capture.
+    pub fn reset(&mut self) {
+        self.enabled = false;
+        self.pulse1 = Pulse::new(true);
+        self.pulse2 = Pulse::new(false);
+        self.wave = Wave::new();
+        self.noise = Noise::new();
+        self.nr50 = 0x00;
+        self.nr51 = 0x00;
+
+        self.frame_step = 0;
+        self.div_bit = false;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        self.wave.save_state(state);
+        self.noise.save_state(state);
+        state.write_u8(self.nr50);
+        state.write_u8(self.nr51);
+
+        state.write_u8(self.frame_step);
+        state.write_bool(self.div_bit);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.wave.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.nr50 = state.read_u8()?;
+        self.nr51 = state.read_u8()?;
+
+        self.frame_step = state.read_u8()?;
+        self.div_bit = state.read_bool()?;
+
+        if self.frame_step > 7 {
+            return Err(StateError::InvalidValue("APU frame sequencer step"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clocks the frame sequencer `steps` times.
+    fn clock_frame_sequencer(apu: &mut APU, steps: usize) {
+        for _ in 0..steps {
+            apu.tick_m_cycle(true);
+            apu.tick_m_cycle(false);
+        }
+    }
+
+    #[test]
+    fn it_should_read_back_with_the_unused_bits_set() {
+        let mut apu = APU::new();
+        apu.write(0xFF26, 0x80);
+
+        assert_eq!(0x80, apu.read(0xFF10));
+        assert_eq!(0x3F, apu.read(0xFF11));
+        assert_eq!(0xFF, apu.read(0xFF13));
+        assert_eq!(0xFF, apu.read(0xFF15));
+        assert_eq!(0x7F, apu.read(0xFF1A));
+        assert_eq!(0x9F, apu.read(0xFF1C));
+        assert_eq!(0xF0, apu.read(0xFF26));
+        assert_eq!(0xFF, apu.read(0xFF27));
+    }
+
+    #[test]
+    fn it_should_turn_a_channel_off_when_its_length_runs_out() {
+        let mut apu = APU::new();
+        apu.write(0xFF26, 0x80);
+
+        apu.write(0xFF21, 0xF0);
+        apu.write(0xFF20, 0x3E);
+        apu.write(0xFF23, 0xC0);
+
+        assert_eq!(0xF8, apu.read(0xFF26));
+
+        // A length of 2 is clocked on steps 0 and 2
+        clock_frame_sequencer(&mut apu, 2);
+        assert_eq!(0xF8, apu.read(0xFF26));
+
+        clock_frame_sequencer(&mut apu, 1);
+        assert_eq!(0xF0, apu.read(0xFF26));
+    }
+
+    #[test]
+    fn it_should_clear_the_registers_when_powered_off() {
+        let mut apu = APU::post_boot(Model::DMG);
+        apu.write(0xFF30, 0x12);
+
+        assert_eq!(0xF1, apu.read(0xFF26));
+
+        apu.write(0xFF26, 0x00);
+
+        assert_eq!(0x70, apu.read(0xFF26));
+        assert_eq!(0x00, apu.read(0xFF24));
+        assert_eq!(0x00, apu.read(0xFF25));
+        assert_eq!(0x00, apu.read(0xFF12));
+        assert_eq!(0x12, apu.read(0xFF30));
+
+        // Writes are ignored while it's off, apart from wave RAM
+        apu.write(0xFF25, 0xFF);
+        apu.write(0xFF31, 0x34);
+
+        assert_eq!(0x00, apu.read(0xFF25));
+        assert_eq!(0x34, apu.read(0xFF31));
+    }
+
+    #[test]
+    fn it_should_turn_the_first_pulse_channel_off_when_the_sweep_overflows() {
+        let mut apu = APU::new();
+        apu.write(0xFF26, 0x80);
+
+        apu.write(0xFF10, 0x11);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF13, 0x00);
+        apu.write(0xFF14, 0x85);
+
+        assert!(apu.read(0xFF26).bit(0));
+
+        // The first sweep clock at step 2 raises 0x500 to 0x780, and the check of
+        // the frequency after that overflows
+        clock_frame_sequencer(&mut apu, 3);
+
+        assert!(!apu.read(0xFF26).bit(0));
+    }
+
+    #[test]
+    fn it_should_shift_the_noise_lfsr() {
+        let mut noise = Noise::new();
+        noise.write(2, 0xF0, false);
+        noise.write(4, 0x80, false);
+
+        assert_eq!(0, noise.output());
+
+        // Zeroes are fed in at the top until the first one reaches bit 0 on the
+        // 15th shift
+        for _ in 0..14 * 8 {
+            noise.tick();
+        }
+
+        assert_eq!(0, noise.output());
+
+        for _ in 0..8 {
+            noise.tick();
+        }
+
+        assert_eq!(15, noise.output());
+    }
+
+    #[test]
+    fn it_should_pan_the_channels() {
+        let mut apu = APU::new();
+        apu.write(0xFF26, 0x80);
+        apu.write(0xFF24, 0x77);
+
+        // The pulse channel starts low at the start of the duty cycle
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF19, 0x80);
+
+        apu.write(0xFF25, 0x02);
+        assert_eq!((0.0, -0.25), apu.output());
+
+        apu.write(0xFF25, 0x20);
+        assert_eq!((-0.25, 0.0), apu.output());
+    }
+
+    #[test]
+    fn it_should_mute_and_solo_channels() {
+        let mut apu = APU::new();
+        apu.write(0xFF26, 0x80);
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0x03);
+
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x80);
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF19, 0x80);
+
+        assert_eq!((0.0, -0.5), apu.output());
+
+        apu.set_muted(Channel::Pulse1, true);
+        assert_eq!((0.0, -0.25), apu.output());
+
+        // Unmuted, Pulse1 still drops out while only Pulse2 is soloed
+        apu.set_muted(Channel::Pulse1, false);
+        apu.set_soloed(Channel::Pulse2, true);
+        assert_eq!((0.0, -0.25), apu.output());
+
+        apu.set_soloed(Channel::Wave, true);
+        assert_eq!((0.0, -0.25), apu.output());
+
+        // Muting wins over soloing
+        apu.set_muted(Channel::Pulse2, true);
+        assert_eq!((0.0, 0.0), apu.output());
+    }
+}
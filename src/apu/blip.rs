@@ -0,0 +1,177 @@
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+
+// Each step is spread over WIDTH output samples, at one of PHASES offsets
+// between two samples
+const WIDTH: usize = 16;
+const HALF_WIDTH: usize = WIDTH / 2;
+const PHASES: usize = 32;
+
+// The cutoff as a fraction of the output rate, a little under Nyquist
+const CUTOFF: f64 = 0.45;
+
+/// Resamples a signal by adding each change in amplitude as a band-limited
+/// step, then summing the steps back up when reading it out. This keeps the
+/// aliasing of the sharp edges of the channels out of the output.
+pub struct BlipBuffer {
+    kernel: Vec<[f32; WIDTH]>,
+    // Output samples per input clock
+    ratio: f64,
+    // The current clock in output samples from the start of `deltas`
+    time: f64,
+    deltas: Vec<f32>,
+    amplitude: f32,
+    sum: f32,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> Self {
+        BlipBuffer {
+            kernel: step_kernel(),
+            ratio: f64::from(sample_rate) / f64::from(clock_rate),
+            time: HALF_WIDTH as f64,
+            deltas: Vec::new(),
+            amplitude: 0.0,
+            sum: 0.0,
+        }
+    }
+
+    pub fn set_rates(&mut self, clock_rate: u32, sample_rate: u32) {
+        self.ratio = f64::from(sample_rate) / f64::from(clock_rate);
+    }
+
+    /// Sets the amplitude of the signal from the current clock on.
+    pub fn set_amplitude(&mut self, amplitude: f32) {
+        let delta = amplitude - self.amplitude;
+
+        if delta == 0.0 {
+            return;
+        }
+
+        self.amplitude = amplitude;
+
+        let position = self.time.floor();
+        let phase = ((self.time - position) * PHASES as f64) as usize;
+        let start = position as usize + 1 - HALF_WIDTH;
+
+        if self.deltas.len() < start + WIDTH {
+            self.deltas.resize(start + WIDTH, 0.0);
+        }
+
+        for (delta_sum, step) in self.deltas[start..start + WIDTH]
+            .iter_mut()
+            .zip(self.kernel[phase].iter())
+        {
+            *delta_sum += step * delta;
+        }
+    }
+
+    pub fn clock(&mut self) {
+        self.time += self.ratio;
+    }
+
+    /// The number of output samples no later step can change.
+    pub fn samples_available(&self) -> usize {
+        self.time as usize + 1 - HALF_WIDTH
+    }
+
+    /// Reads out the samples that are ready.
+    pub fn read_samples(&mut self, mut output: impl FnMut(f32)) {
+        let count = self.samples_available();
+
+        if self.deltas.len() < count {
+            self.deltas.resize(count, 0.0);
+        }
+
+        for delta in self.deltas.drain(..count) {
+            self.sum += delta;
+            output(self.sum);
+        }
+
+        self.time -= count as f64;
+    }
+}
+
+/// The band-limited step at each phase, as the difference between each
+/// sample so that the running sum of them rises from 0.0 to 1.0.
+fn step_kernel() -> Vec<[f32; WIDTH]> {
+    // The integral of a windowed sinc, every 1 / PHASES of a sample
+    let mut integral = Vec::with_capacity(WIDTH * PHASES + 1);
+    let mut sum = 0.0;
+    integral.push(0.0);
+
+    for i in 0..WIDTH * PHASES {
+        let t = (i as f64 + 0.5) / PHASES as f64 - HALF_WIDTH as f64;
+        let x = 2.0 * CUTOFF * t;
+        let sinc = if x == 0.0 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        };
+        let window = 0.42
+            + 0.5 * (2.0 * PI * t / WIDTH as f64).cos()
+            + 0.08 * (4.0 * PI * t / WIDTH as f64).cos();
+
+        sum += 2.0 * CUTOFF * sinc * window / PHASES as f64;
+        integral.push(sum);
+    }
+
+    (0..PHASES)
+        .map(|phase| {
+            let step = |i: usize| integral[((i + 1) * PHASES).saturating_sub(phase)] / sum;
+            let mut steps = [0.0; WIDTH];
+
+            for (i, value) in steps.iter_mut().enumerate() {
+                let previous = if i == 0 { 0.0 } else { step(i - 1) };
+                *value = (step(i) - previous) as f32;
+            }
+
+            // Whatever is left makes sure each step adds up to exactly 1.0
+            steps[WIDTH - 1] += (1.0 - step(WIDTH - 1)) as f32;
+
+            steps
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_make_the_output_rate_of_samples() {
+        let mut blip = BlipBuffer::new(1_048_576, 48000);
+        let mut count = 0;
+
+        for _ in 0..1_048_576 {
+            blip.clock();
+        }
+
+        blip.read_samples(|_| count += 1);
+
+        // Give or take the samples still waiting on later steps
+        assert!((47999..=48001).contains(&count));
+    }
+
+    #[test]
+    fn it_should_settle_on_the_amplitude_of_a_step() {
+        let mut blip = BlipBuffer::new(1_048_576, 48000);
+        let mut samples = Vec::new();
+
+        for i in 0..4096 {
+            if i == 100 {
+                blip.set_amplitude(0.5);
+            }
+
+            blip.clock();
+        }
+
+        blip.read_samples(|sample| samples.push(sample));
+
+        assert!(samples[..4].iter().all(|&sample| sample == 0.0));
+        assert!((samples.last().unwrap() - 0.5).abs() < 1e-6);
+
+        // The edge is smoothed over more than one sample
+        assert!(samples.iter().any(|&sample| sample > 0.05 && sample < 0.45));
+    }
+}
@@ -0,0 +1,87 @@
+use bitfield::{Bit, BitRange};
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// The volume envelope of NRx2, which steps the volume up or down every
+/// `period` clocks of the frame sequencer.
+pub struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Envelope {
+            initial_volume: 0,
+            increase: false,
+            period: 0,
+
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// The DAC is off when both the initial volume and the direction are 0.
+    pub fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.increase
+    }
+
+    pub fn read(&self) -> u8 {
+        self.initial_volume << 4 | (self.increase as u8) << 3 | self.period
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.initial_volume = value.bit_range(7, 4);
+        self.increase = value.bit(3);
+        self.period = value.bit_range(2, 0);
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+
+        if self.timer == 0 {
+            self.timer = self.period;
+
+            match self.volume {
+                0..=14 if self.increase => self.volume += 1,
+                1..=15 if !self.increase => self.volume -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.read());
+        state.write_u8(self.volume);
+        state.write_u8(self.timer);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.write(state.read_u8()?);
+        self.volume = state.read_u8()?;
+        self.timer = state.read_u8()?;
+
+        if self.volume > 15 {
+            return Err(StateError::InvalidValue("APU envelope volume"));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+/// The capacitor on each output, which blocks the DC offset of the DACs so
+/// the output drifts back to 0.0 when the channels are silent.
+pub struct HighPass {
+    enabled: bool,
+    // The charge kept per T-cycle, and per output sample at the current rate
+    charge_per_cycle: f64,
+    charge: f32,
+    capacitor: f32,
+}
+
+impl HighPass {
+    pub fn new(charge_per_cycle: f64, sample_rate: u32) -> Self {
+        HighPass {
+            enabled: true,
+            charge_per_cycle,
+            charge: charge_per_sample(charge_per_cycle, sample_rate),
+            capacitor: 0.0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.charge = charge_per_sample(self.charge_per_cycle, sample_rate);
+    }
+
+    pub fn filter(&mut self, input: f32) -> f32 {
+        if !self.enabled {
+            return input;
+        }
+
+        let output = input - self.capacitor;
+        self.capacitor = input - output * self.charge;
+
+        output
+    }
+}
+
+fn charge_per_sample(charge_per_cycle: f64, sample_rate: u32) -> f32 {
+    charge_per_cycle.powf(4_194_304.0 / f64::from(sample_rate)) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_block_a_constant_offset() {
+        let mut high_pass = HighPass::new(0.999958, 48000);
+
+        assert_eq!(1.0, high_pass.filter(1.0));
+
+        for _ in 0..48000 {
+            high_pass.filter(1.0);
+        }
+
+        assert!(high_pass.filter(1.0).abs() < 0.001);
+
+        high_pass.set_enabled(false);
+        assert_eq!(1.0, high_pass.filter(1.0));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Turns a channel off once it has played for the length set in NRx1, when
+/// enabled in NRx4.
+pub struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> Self {
+        LengthCounter {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_length(&mut self, value: u8) {
+        self.counter = self.max - u16::from(value);
+    }
+
+    /// Writes the enable bit of NRx4, returning true if the channel should be
+    /// turned off. `extra_clock` is set when the next step of the frame
+    /// sequencer won't clock the length, in which case enabling it clocks it
+    /// straight away.
+    pub fn set_enabled(&mut self, enabled: bool, extra_clock: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enabled;
+
+        if !was_enabled && enabled && extra_clock && self.counter > 0 {
+            self.counter -= 1;
+            self.counter == 0
+        } else {
+            false
+        }
+    }
+
+    pub fn trigger(&mut self, extra_clock: bool) {
+        if self.counter == 0 {
+            self.counter = self.max;
+
+            if self.enabled && extra_clock {
+                self.counter -= 1;
+            }
+        }
+    }
+
+    /// Clocked by the frame sequencer, returns true if the channel should be
+    /// turned off.
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            self.counter == 0
+        } else {
+            false
+        }
+    }
+
+    /// Powering off the APU leaves the length alone on the monochrome models.
+    pub fn power_off(&mut self) {
+        self.enabled = false;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.counter);
+        state.write_bool(self.enabled);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.counter = state.read_u16()?;
+        self.enabled = state.read_bool()?;
+
+        if self.counter > self.max {
+            return Err(StateError::InvalidValue("APU length"));
+        }
+
+        Ok(())
+    }
+}
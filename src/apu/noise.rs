@@ -0,0 +1,180 @@
+use bitfield::{Bit, BitRange};
+
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const DIVISORS: [u16; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// The noise channel, NR41-NR44, which plays the low bit of a linear
+/// feedback shift register.
+pub struct Noise {
+    length: LengthCounter,
+    envelope: Envelope,
+    shift: u8,
+    short_mode: bool,
+    divisor_code: u8,
+
+    enabled: bool,
+    timer: u32,
+    lfsr: u16,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+
+            enabled: false,
+            timer: 0,
+            lfsr: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, value: bool) {
+        self.enabled = value;
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// The digital output from 0 to 15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr.bit(0) {
+            return 0;
+        }
+
+        self.envelope.volume()
+    }
+
+    fn polynomial(&self) -> u8 {
+        self.shift << 4 | (self.short_mode as u8) << 3 | self.divisor_code
+    }
+
+    fn period(&self) -> u32 {
+        u32::from(DIVISORS[usize::from(self.divisor_code)]) << self.shift
+    }
+
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            1 => 0xFF,
+            2 => self.envelope.read(),
+            3 => self.polynomial(),
+            4 => 0xBF | (self.length.enabled() as u8) << 6,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+        match register {
+            1 => self.write_length(value),
+            2 => {
+                self.envelope.write(value);
+
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.shift = value.bit_range(7, 4);
+                self.short_mode = value.bit(3);
+                self.divisor_code = value.bit_range(2, 0);
+            }
+            4 => {
+                if self.length.set_enabled(value.bit(6), extra_length_clock) {
+                    self.enabled = false;
+                }
+
+                if value.bit(7) {
+                    self.enabled = self.dac_enabled();
+                    self.length.trigger(extra_length_clock);
+                    self.envelope.trigger();
+                    self.timer = self.period();
+                    self.lfsr = 0x7FFF;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes the length in NR41, which still works while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.set_length(value.bit_range(5, 0));
+    }
+
+    /// Runs for a T-cycle, shifting the LFSR when the timer runs out.
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+
+        if self.timer > 0 {
+            return;
+        }
+
+        self.timer = self.period();
+
+        // Shifts of 14 and 15 stop the LFSR
+        if self.shift >= 14 {
+            return;
+        }
+
+        let feedback = (self.lfsr ^ self.lfsr >> 1) & 1;
+        self.lfsr = self.lfsr >> 1 | feedback << 14;
+
+        if self.short_mode {
+            self.lfsr = self.lfsr & !0x40 | feedback << 6;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clears every register apart from the length.
+    pub fn power_off(&mut self) {
+        let mut length = LengthCounter::new(64);
+        core::mem::swap(&mut length, &mut self.length);
+        length.power_off();
+
+        *self = Noise {
+            length,
+            ..Noise::new()
+        };
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.write_u8(self.polynomial());
+
+        state.write_bool(self.enabled);
+        state.write_u32(self.timer);
+        state.write_u16(self.lfsr);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.write(3, state.read_u8()?, false);
+
+        self.enabled = state.read_bool()?;
+        self.timer = state.read_u32()?;
+        self.lfsr = state.read_u16()? & 0x7FFF;
+
+        Ok(())
+    }
+}
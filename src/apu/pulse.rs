@@ -0,0 +1,314 @@
+use bitfield::{Bit, BitRange};
+
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+/// The frequency sweep of NR10, which only the first pulse channel has.
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+
+    enabled: bool,
+    timer: u8,
+    shadow_frequency: u16,
+    // Clearing negate after a subtraction since the trigger turns the channel off
+    negate_used: bool,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Sweep {
+            period: 0,
+            negate: false,
+            shift: 0,
+
+            enabled: false,
+            timer: 0,
+            shadow_frequency: 0,
+            negate_used: false,
+        }
+    }
+
+    fn read(&self) -> u8 {
+        0x80 | self.period << 4 | (self.negate as u8) << 3 | self.shift
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    /// The next frequency, which turns the channel off when it's above 2047.
+    fn calculate(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift;
+
+        if self.negate {
+            self.negate_used = true;
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.read());
+        state.write_bool(self.enabled);
+        state.write_u8(self.timer);
+        state.write_u16(self.shadow_frequency);
+        state.write_bool(self.negate_used);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let value = state.read_u8()?;
+        self.period = value.bit_range(6, 4);
+        self.negate = value.bit(3);
+        self.shift = value.bit_range(2, 0);
+
+        self.enabled = state.read_bool()?;
+        self.timer = state.read_u8()?;
+        self.shadow_frequency = state.read_u16()?;
+        self.negate_used = state.read_bool()?;
+
+        Ok(())
+    }
+}
+
+/// A square wave channel, NR10-NR14 for the first with its sweep and
+/// NR21-NR24 for the second.
+pub struct Pulse {
+    sweep: Option<Sweep>,
+    duty: u8,
+    length: LengthCounter,
+    envelope: Envelope,
+    frequency: u16,
+
+    enabled: bool,
+    timer: u16,
+    duty_position: u8,
+}
+
+impl Pulse {
+    pub fn new(sweep: bool) -> Self {
+        Pulse {
+            sweep: if sweep { Some(Sweep::new()) } else { None },
+            duty: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            frequency: 0,
+
+            enabled: false,
+            timer: 0,
+            duty_position: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, value: bool) {
+        self.enabled = value;
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    pub fn frequency(&self) -> u16 {
+        self.frequency
+    }
+
+    /// The digital output from 0 to 15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        DUTY_CYCLES[usize::from(self.duty)][usize::from(self.duty_position)]
+            * self.envelope.volume()
+    }
+
+    /// Reads NRx0-NRx4, the frequency can't be read back.
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            0 => self.sweep.as_ref().map(Sweep::read).unwrap_or(0xFF),
+            1 => self.duty << 6 | 0x3F,
+            2 => self.envelope.read(),
+            3 => 0xFF,
+            4 => 0xBF | (self.length.enabled() as u8) << 6,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.period = value.bit_range(6, 4);
+                    sweep.negate = value.bit(3);
+                    sweep.shift = value.bit_range(2, 0);
+
+                    if !sweep.negate && sweep.negate_used {
+                        self.enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = value.bit_range(7, 6);
+                self.write_length(value);
+            }
+            2 => {
+                self.envelope.write(value);
+
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = self.frequency & 0x0700 | u16::from(value),
+            4 => {
+                self.frequency = self.frequency & 0x00FF | u16::from(value & 0x07) << 8;
+
+                if self.length.set_enabled(value.bit(6), extra_length_clock) {
+                    self.enabled = false;
+                }
+
+                if value.bit(7) {
+                    self.trigger(extra_length_clock);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes the length in NRx1, which still works while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.set_length(value.bit_range(5, 0));
+    }
+
+    fn trigger(&mut self, extra_length_clock: bool) {
+        self.enabled = self.dac_enabled();
+        self.length.trigger(extra_length_clock);
+        self.timer = (2048 - self.frequency) * 4;
+        self.envelope.trigger();
+
+        let frequency = self.frequency;
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow_frequency = frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            sweep.negate_used = false;
+
+            // The overflow check happens straight away when there's a shift
+            if sweep.shift != 0 && sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Runs for a T-cycle, stepping through the duty cycle.
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+
+        if self.timer == 0 {
+            self.timer = (2048 - self.frequency) * 4;
+            self.duty_position = (self.duty_position + 1) % 8;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let sweep = match &mut self.sweep {
+            Some(sweep) => sweep,
+            None => return,
+        };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+
+        if sweep.timer > 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        let frequency = sweep.calculate();
+
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if sweep.shift != 0 {
+            sweep.shadow_frequency = frequency;
+            self.frequency = frequency;
+
+            // The new frequency is checked for overflow again, but not used
+            if sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Clears every register apart from the length.
+    pub fn power_off(&mut self) {
+        let mut length = LengthCounter::new(64);
+        core::mem::swap(&mut length, &mut self.length);
+        length.power_off();
+
+        *self = Pulse {
+            length,
+            ..Pulse::new(self.sweep.is_some())
+        };
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(state);
+        }
+
+        state.write_u8(self.duty);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.write_u16(self.frequency);
+
+        state.write_bool(self.enabled);
+        state.write_u16(self.timer);
+        state.write_u8(self.duty_position);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load_state(state)?;
+        }
+
+        self.duty = state.read_u8()? & 0x03;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.frequency = state.read_u16()? & 0x07FF;
+
+        self.enabled = state.read_bool()?;
+        self.timer = state.read_u16()?;
+        self.duty_position = state.read_u8()? & 0x07;
+
+        Ok(())
+    }
+}
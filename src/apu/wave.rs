@@ -0,0 +1,195 @@
+use bitfield::{Bit, BitRange};
+
+use super::length::LengthCounter;
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// The wave channel, NR30-NR34, which plays the 32 4-bit samples in wave RAM.
+pub struct Wave {
+    dac_enabled: bool,
+    length: LengthCounter,
+    volume_code: u8,
+    frequency: u16,
+
+    enabled: bool,
+    timer: u16,
+    position: u8,
+    sample: u8,
+    ram: [u8; 16],
+}
+
+impl Wave {
+    pub fn new() -> Self {
+        Wave {
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            volume_code: 0,
+            frequency: 0,
+
+            enabled: false,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            ram: [0; 16],
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, value: bool) {
+        self.enabled = value;
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    pub fn frequency(&self) -> u16 {
+        self.frequency
+    }
+
+    /// The digital output from 0 to 15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        match self.volume_code {
+            0 => self.sample >> 4,
+            1 => self.sample,
+            2 => self.sample >> 1,
+            3 => self.sample >> 2,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            0 => 0x7F | (self.dac_enabled as u8) << 7,
+            1 => 0xFF,
+            2 => 0x9F | self.volume_code << 5,
+            3 => 0xFF,
+            4 => 0xBF | (self.length.enabled() as u8) << 6,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+        match register {
+            0 => {
+                self.dac_enabled = value.bit(7);
+
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.write_length(value),
+            2 => self.volume_code = value.bit_range(6, 5),
+            3 => self.frequency = self.frequency & 0x0700 | u16::from(value),
+            4 => {
+                self.frequency = self.frequency & 0x00FF | u16::from(value & 0x07) << 8;
+
+                if self.length.set_enabled(value.bit(6), extra_length_clock) {
+                    self.enabled = false;
+                }
+
+                if value.bit(7) {
+                    self.enabled = self.dac_enabled;
+                    self.length.trigger(extra_length_clock);
+                    self.timer = (2048 - self.frequency) * 2;
+                    self.position = 0;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes the length in NR31, which still works while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.set_length(value);
+    }
+
+    /// While the channel plays, wave RAM can only be accessed at the byte
+    /// being played.
+    pub fn read_ram(&self, offset: u16) -> u8 {
+        if self.enabled {
+            self.ram[usize::from(self.position / 2)]
+        } else {
+            self.ram[usize::from(offset)]
+        }
+    }
+
+    pub fn write_ram(&mut self, offset: u16, value: u8) {
+        if self.enabled {
+            self.ram[usize::from(self.position / 2)] = value;
+        } else {
+            self.ram[usize::from(offset)] = value;
+        }
+    }
+
+    /// Runs for a T-cycle, moving on to the next sample.
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+
+        if self.timer == 0 {
+            self.timer = (2048 - self.frequency) * 2;
+            self.position = (self.position + 1) % 32;
+
+            let byte = self.ram[usize::from(self.position / 2)];
+
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Clears every register apart from the length and wave RAM.
+    pub fn power_off(&mut self) {
+        let mut length = LengthCounter::new(256);
+        core::mem::swap(&mut length, &mut self.length);
+        length.power_off();
+
+        *self = Wave {
+            length,
+            ram: self.ram,
+            ..Wave::new()
+        };
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.dac_enabled);
+        self.length.save_state(state);
+        state.write_u8(self.volume_code);
+        state.write_u16(self.frequency);
+
+        state.write_bool(self.enabled);
+        state.write_u16(self.timer);
+        state.write_u8(self.position);
+        state.write_u8(self.sample);
+        state.write_bytes(&self.ram);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.dac_enabled = state.read_bool()?;
+        self.length.load_state(state)?;
+        self.volume_code = state.read_u8()? & 0x03;
+        self.frequency = state.read_u16()? & 0x07FF;
+
+        self.enabled = state.read_bool()?;
+        self.timer = state.read_u16()?;
+        self.position = state.read_u8()? & 0x1F;
+        self.sample = state.read_u8()? & 0x0F;
+        state.read_bytes_into(&mut self.ram, "wave RAM")?;
+
+        Ok(())
+    }
+}
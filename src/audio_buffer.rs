@@ -0,0 +1,78 @@
+use alloc::vec::Vec;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+/// The samples made by the APU, as interleaved left and right pairs from
+/// -1.0 to 1.0.
+#[derive(Clone)]
+pub struct AudioBuffer {
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+impl Default for AudioBuffer {
+    fn default() -> Self {
+        AudioBuffer::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl AudioBuffer {
+    pub fn new(sample_rate: u32) -> Self {
+        AudioBuffer {
+            sample_rate,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// The number of left and right pairs.
+    pub fn len(&self) -> usize {
+        self.samples.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn push(&mut self, left: f32, right: f32) {
+        self.samples.push(left);
+        self.samples.push(right);
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn to_i16(&self) -> Vec<i16> {
+        self.samples
+            .iter()
+            .map(|sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_convert_to_i16() {
+        let mut audio_buffer = AudioBuffer::new(44100);
+        audio_buffer.push(0.5, -1.0);
+        audio_buffer.push(2.0, 0.0);
+
+        assert_eq!(2, audio_buffer.len());
+        assert_eq!(vec![16383, -32767, 32767, 0], audio_buffer.to_i16());
+    }
+}
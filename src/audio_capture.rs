@@ -0,0 +1,116 @@
+use alloc::vec::Vec;
+
+use crate::apu::Channel;
+
+/// The audio recorded since a capture was started, as the mixed left and
+/// right output and, if asked for, each channel on its own before panning,
+/// muting and the master volume. Samples are from -1.0 to 1.0.
+pub struct AudioCapture {
+    sample_rate: u32,
+    mix: Vec<f32>,
+    channels: Option<[Vec<f32>; 4]>,
+}
+
+impl AudioCapture {
+    pub fn new(sample_rate: u32, channels: bool) -> Self {
+        AudioCapture {
+            sample_rate,
+            mix: Vec::new(),
+            channels: if channels {
+                Some([Vec::new(), Vec::new(), Vec::new(), Vec::new()])
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Interleaved left and right pairs.
+    pub fn mix(&self) -> &[f32] {
+        &self.mix
+    }
+
+    pub fn channel(&self, channel: Channel) -> Option<&[f32]> {
+        self.channels
+            .as_ref()
+            .map(|channels| channels[channel as usize].as_slice())
+    }
+
+    pub fn push_mix(&mut self, samples: &[f32]) {
+        self.mix.extend_from_slice(samples);
+    }
+
+    pub fn push_channel(&mut self, channel: usize, sample: f32) {
+        if let Some(channels) = &mut self.channels {
+            channels[channel].push(sample);
+        }
+    }
+
+    /// The mix as a 16-bit stereo WAV file.
+    pub fn mix_to_wav(&self) -> Vec<u8> {
+        encode_wav(&self.mix, 2, self.sample_rate)
+    }
+
+    /// A channel as a 16-bit mono WAV file, if channels were captured.
+    pub fn channel_to_wav(&self, channel: Channel) -> Option<Vec<u8>> {
+        self.channel(channel)
+            .map(|samples| encode_wav(samples, 1, self.sample_rate))
+    }
+}
+
+fn encode_wav(samples: &[f32], channels: u16, sample_rate: u32) -> Vec<u8> {
+    let block_align = channels * 2;
+    let data_len = samples.len() as u32 * 2;
+    let mut data = Vec::with_capacity(44 + samples.len() * 2);
+
+    data.extend_from_slice(b"RIFF");
+    data.extend_from_slice(&(36 + data_len).to_le_bytes());
+    data.extend_from_slice(b"WAVE");
+
+    data.extend_from_slice(b"fmt ");
+    data.extend_from_slice(&16u32.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    data.extend_from_slice(&channels.to_le_bytes());
+    data.extend_from_slice(&sample_rate.to_le_bytes());
+    data.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    data.extend_from_slice(&block_align.to_le_bytes());
+    data.extend_from_slice(&16u16.to_le_bytes());
+
+    data.extend_from_slice(b"data");
+    data.extend_from_slice(&data_len.to_le_bytes());
+
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+        data.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_encode_a_wav_file() {
+        let mut capture = AudioCapture::new(48000, false);
+        capture.push_mix(&[1.0, -1.0]);
+
+        let wav = capture.mix_to_wav();
+
+        assert_eq!(48, wav.len());
+        assert_eq!(b"RIFF", &wav[0..4]);
+        assert_eq!(40, u32::from_le_bytes([wav[4], wav[5], wav[6], wav[7]]));
+        assert_eq!([2, 0], wav[22..24]);
+        assert_eq!(
+            48000,
+            u32::from_le_bytes([wav[24], wav[25], wav[26], wav[27]])
+        );
+        assert_eq!([0xFF, 0x7F, 0x01, 0x80], wav[44..48]);
+
+        assert_eq!(None, capture.channel_to_wav(Channel::Noise));
+    }
+}
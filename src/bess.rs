@@ -0,0 +1,298 @@
+use alloc::vec::Vec;
+use std::convert::TryInto;
+
+use crate::bus::Bus;
+use crate::cpu::{Registers, CPU};
+use crate::state::StateError;
+use crate::Model;
+
+// BESS (Best Effort Save State) is a block based format that is appended to
+// an emulator's own save state, found through a footer at the end of the
+// file: the offset of the first block followed by "BESS".
+const FOOTER_MAGIC: &[u8; 4] = b"BESS";
+const FOOTER_LEN: usize = 8;
+
+const BLOCK_HEADER_LEN: usize = 8;
+const INFO_LEN: usize = 0x12;
+const CORE_LEN: usize = 0xD0;
+const RTC_LEN: usize = 0x30;
+
+const CORE_MAJOR_VERSION: u16 = 1;
+const CORE_MINOR_VERSION: u16 = 1;
+
+const EXECUTION_RUNNING: u8 = 0;
+const EXECUTION_HALTED: u8 = 1;
+
+fn model_id(model: Model) -> &'static [u8; 4] {
+    match model {
+        Model::DMG0 => b"GD0 ",
+        Model::DMG => b"GDB ",
+        Model::MGB => b"GM  ",
+        Model::SGB => b"SN  ",
+        Model::SGB2 => b"S2  ",
+        Model::CGB => b"CCE ",
+        Model::AGB => b"CAA ",
+    }
+}
+
+fn model_from_id(id: &[u8]) -> Result<Model, StateError> {
+    // The third character is the revision, which only matters for the DMG0
+    match (id[0], id[1], id[2]) {
+        (b'G', b'D', b'0') => Ok(Model::DMG0),
+        (b'G', b'D', _) => Ok(Model::DMG),
+        (b'G', b'M', _) => Ok(Model::MGB),
+        (b'S', b'N', _) | (b'S', b'P', _) => Ok(Model::SGB),
+        (b'S', b'2', _) => Ok(Model::SGB2),
+        (b'C', b'C', _) => Ok(Model::CGB),
+        (b'C', b'A', _) => Ok(Model::AGB),
+        _ => Err(StateError::InvalidValue("BESS model")),
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    // Only used for sizes and offsets so it's returned ready to index with
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+}
+
+fn write_block(data: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    data.extend_from_slice(id);
+    data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    data.extend_from_slice(body);
+}
+
+/// Writes the state of the machine as a standalone BESS file.
+pub fn export(cpu: &CPU<Bus>, model: Model) -> Vec<u8> {
+    let bus = cpu.bus();
+    let cartridge = bus.cartridge();
+    let rom = cartridge.rom();
+
+    let mut data = Vec::new();
+
+    // The memory regions are stored ahead of the blocks and referenced from CORE
+    let mut buffer = |contents: &[u8]| {
+        let offset = data.len();
+        data.extend_from_slice(contents);
+
+        (contents.len() as u32, offset as u32)
+    };
+
+    let buffers = [
+        buffer(bus.wram()),
+        buffer(bus.ppu().vram()),
+        buffer(cartridge.ram_data()),
+        buffer(bus.ppu().oam()),
+        buffer(bus.hram()),
+        // Background and object palettes only exist on the CGB
+        (0, 0),
+        (0, 0),
+    ];
+
+    let first_block = data.len() as u32;
+
+    write_block(
+        &mut data,
+        b"NAME",
+        concat!("gb ", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+
+    let mut info: Vec<u8> = (0x0134..=0x0143).map(|addr| rom.read(addr)).collect();
+    info.extend_from_slice(&[rom.read(0x014E), rom.read(0x014F)]);
+    write_block(&mut data, b"INFO", &info);
+
+    let registers = cpu.registers();
+    let mut core = Vec::with_capacity(CORE_LEN);
+
+    core.extend_from_slice(&CORE_MAJOR_VERSION.to_le_bytes());
+    core.extend_from_slice(&CORE_MINOR_VERSION.to_le_bytes());
+    core.extend_from_slice(model_id(model));
+
+    for register in &[
+        registers.pc(),
+        registers.af(),
+        registers.bc(),
+        registers.de(),
+        registers.hl(),
+        registers.sp(),
+    ] {
+        core.extend_from_slice(&register.to_le_bytes());
+    }
+
+    core.push(cpu.ime() as u8);
+    core.push(bus.interrupts().inte().into());
+    core.push(if cpu.halt() {
+        EXECUTION_HALTED
+    } else {
+        EXECUTION_RUNNING
+    });
+    core.push(0);
+
+    core.extend_from_slice(&bus.io_registers());
+
+    for (size, offset) in &buffers {
+        core.extend_from_slice(&size.to_le_bytes());
+        core.extend_from_slice(&offset.to_le_bytes());
+    }
+
+    write_block(&mut data, b"CORE", &core);
+
+    let mbc: Vec<u8> = cartridge
+        .register_writes()
+        .into_iter()
+        .flat_map(|(addr, value)| {
+            let [low, high] = addr.to_le_bytes();
+            vec![low, high, value]
+        })
+        .collect();
+
+    if !mbc.is_empty() {
+        write_block(&mut data, b"MBC ", &mbc);
+    }
+
+    if let Some(rtc) = cartridge.rtc() {
+        // The block has the same layout as the footer of a .sav file
+        write_block(&mut data, b"RTC ", &rtc.save_footer(bus.unix_time()));
+    }
+
+    write_block(&mut data, b"END ", &[]);
+
+    data.extend_from_slice(&first_block.to_le_bytes());
+    data.extend_from_slice(FOOTER_MAGIC);
+
+    data
+}
+
+/// Restores the state of the machine from the BESS blocks at the end of
+/// `data`, returning the model the state was captured on. Blocks that are
+/// not understood are skipped.
+pub fn import(cpu: &mut CPU<Bus>, data: &[u8]) -> Result<Model, StateError> {
+    if data.len() < FOOTER_LEN || &data[data.len() - 4..] != FOOTER_MAGIC {
+        return Err(StateError::InvalidMagic);
+    }
+
+    let blocks_end = data.len() - FOOTER_LEN;
+    let mut offset = read_u32(data, blocks_end);
+
+    let (mut info, mut core, mut mbc, mut rtc) = (None, None, None, None);
+
+    loop {
+        if offset + BLOCK_HEADER_LEN > blocks_end {
+            return Err(StateError::UnexpectedEnd);
+        }
+
+        let id = &data[offset..offset + 4];
+        let len = read_u32(data, offset + 4);
+        let start = offset + BLOCK_HEADER_LEN;
+
+        if start + len > blocks_end {
+            return Err(StateError::UnexpectedEnd);
+        }
+
+        let body = &data[start..start + len];
+
+        match id {
+            b"END " => break,
+            b"INFO" => info = Some(body),
+            b"CORE" => core = Some(body),
+            b"MBC " => mbc = Some(body),
+            b"RTC " => rtc = Some(body),
+            _ => {}
+        }
+
+        offset = start + len;
+    }
+
+    let core = match core {
+        Some(core) if core.len() >= CORE_LEN => core,
+        _ => return Err(StateError::InvalidValue("BESS CORE block")),
+    };
+
+    if read_u16(core, 0x00) != CORE_MAJOR_VERSION {
+        return Err(StateError::UnsupportedVersion(u32::from(read_u16(
+            core, 0x00,
+        ))));
+    }
+
+    if let Some(info) = info {
+        let rom = cpu.bus().cartridge().rom();
+        let title = (0x0134..=0x0143).map(|addr| rom.read(addr));
+        let checksum = [rom.read(0x014E), rom.read(0x014F)];
+
+        if info.len() != INFO_LEN
+            || !title.eq(info[..0x10].iter().cloned())
+            || info[0x10..] != checksum
+        {
+            return Err(StateError::WrongRom);
+        }
+    }
+
+    if mbc.map(|mbc| mbc.len() % 3 != 0).unwrap_or(false) {
+        return Err(StateError::InvalidValue("BESS MBC block"));
+    }
+
+    let model = model_from_id(&core[0x04..0x08])?;
+
+    let buffer = |index: usize| {
+        let size = read_u32(core, 0x98 + index * 8);
+        let offset = read_u32(core, 0x9C + index * 8);
+
+        data.get(offset..offset + size)
+            .ok_or(StateError::InvalidValue("BESS buffer"))
+    };
+
+    let (wram, vram, cartridge_ram, oam, hram) =
+        (buffer(0)?, buffer(1)?, buffer(2)?, buffer(3)?, buffer(4)?);
+
+    let mut registers = Registers::power_on();
+    registers.set_pc(read_u16(core, 0x08));
+    registers.set_af(read_u16(core, 0x0A));
+    registers.set_bc(read_u16(core, 0x0C));
+    registers.set_de(read_u16(core, 0x0E));
+    registers.set_hl(read_u16(core, 0x10));
+    registers.set_sp(read_u16(core, 0x12));
+
+    cpu.set_registers(registers);
+    cpu.set_ime(core[0x14] != 0);
+    // Stopped is treated as halted
+    cpu.set_halt(core[0x16] != EXECUTION_RUNNING);
+
+    let bus = cpu.bus_mut();
+    bus.interrupts_mut().set_inte(core[0x15]);
+    bus.restore_io_registers(core[0x18..0x98].try_into().unwrap());
+
+    // The colour models have larger banked memories, only the first banks are used
+    let copy = |to: &mut [u8], from: &[u8]| {
+        let len = to.len().min(from.len());
+        to[..len].copy_from_slice(&from[..len]);
+    };
+
+    copy(bus.wram_mut(), wram);
+    copy(bus.ppu_mut().vram_mut(), vram);
+    copy(bus.ppu_mut().oam_mut(), oam);
+    copy(bus.hram_mut(), hram);
+    bus.cartridge_mut().restore_ram(cartridge_ram);
+
+    if let Some(mbc) = mbc {
+        for write in mbc.chunks(3) {
+            match u16::from_le_bytes([write[0], write[1]]) {
+                addr @ 0x0000..=0x7FFF | addr @ 0xA000..=0xBFFF => {
+                    bus.write_cartridge(addr, write[2])
+                }
+                _ => return Err(StateError::InvalidValue("BESS MBC block")),
+            }
+        }
+    }
+
+    let now = bus.unix_time();
+
+    if let (Some(rtc), Some(rtc_mut)) = (rtc, bus.cartridge_mut().rtc_mut()) {
+        if rtc.len() == RTC_LEN {
+            rtc_mut.load_save_footer(rtc, now);
+        }
+    }
+
+    Ok(model)
+}
@@ -1,53 +1,133 @@
 use alloc::rc::Rc;
 use core::cell::RefCell;
-use std::convert::TryInto;
 
+use super::apu::APU;
 use super::cartridge::Cartridge;
+use super::dma::DMA;
 use super::hal::HAL;
 use super::interrupts::Interrupts;
 use super::joypad::Joypad;
 use super::ppu::PPU;
+use super::rom::BOOT_ROM_SIZE;
 use super::serial::Serial;
+use super::state::{StateError, StateReader, StateWriter};
 use super::timer::Timer;
 use super::Interrupt;
+use super::Model;
 
 pub struct Bus {
+    boot_rom: Option<Vec<u8>>,
     cartridge: Cartridge,
     ppu: PPU,
+    dma: DMA,
+    apu: APU,
     wram: [u8; 8192],
     joypad: Joypad,
     serial: Serial,
     timer: Timer,
     interrupts: Interrupts,
     hram: [u8; 127],
+
+    hal: Rc<RefCell<dyn HAL>>,
 }
 
 impl Bus {
     pub fn with_cartridge(cartridge: Cartridge, hal: Rc<RefCell<dyn HAL>>) -> Self {
+        Bus::with_model(cartridge, Model::default(), hal)
+    }
+
+    pub fn with_model(cartridge: Cartridge, model: Model, hal: Rc<RefCell<dyn HAL>>) -> Self {
         Bus {
+            boot_rom: None,
             cartridge,
-            ppu: PPU::new(hal.clone()),
+            ppu: PPU::post_boot(model, hal.clone()),
+            dma: DMA::new(),
+            apu: APU::post_boot(model),
             wram: [0; 8192],
             joypad: Joypad::new(hal.clone()),
+            serial: Serial::post_boot(model, hal.clone()),
+            timer: Timer::post_boot(model),
+            interrupts: Interrupts::post_boot(),
+            hram: [0; 127],
+
+            hal,
+        }
+    }
+
+    /// Maps the boot ROM over 0x0000-0x00FF and starts the hardware from its
+    /// power on state, rather than the state the boot ROM leaves it in.
+    pub fn with_boot_rom(
+        cartridge: Cartridge,
+        boot_rom: Vec<u8>,
+        hal: Rc<RefCell<dyn HAL>>,
+    ) -> Self {
+        Bus {
+            boot_rom: Some(boot_rom),
+            ppu: PPU::power_on(hal.clone()),
+            apu: APU::new(),
             serial: Serial::new(hal.clone()),
-            timer: Timer::new(),
+            timer: Timer::power_on(),
             interrupts: Interrupts::new(),
-            hram: [0; 127],
+            ..Bus::with_cartridge(cartridge, hal)
         }
     }
 
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
     pub fn cartridge(&self) -> &Cartridge {
         &self.cartridge
     }
 
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    pub fn unix_time(&self) -> u64 {
+        self.hal.borrow().unix_time()
+    }
+
     pub fn ppu(&self) -> &PPU {
         &self.ppu
     }
 
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &APU {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut APU {
+        &mut self.apu
+    }
+
+    pub fn wram(&self) -> &[u8] {
+        &self.wram
+    }
+
+    pub fn wram_mut(&mut self) -> &mut [u8] {
+        &mut self.wram
+    }
+
+    pub fn hram(&self) -> &[u8] {
+        &self.hram
+    }
+
+    pub fn hram_mut(&mut self) -> &mut [u8] {
+        &mut self.hram
+    }
+
     pub fn joypad(&self) -> &Joypad {
         &self.joypad
     }
 
+    pub fn joypad_mut(&mut self) -> &mut Joypad {
+        &mut self.joypad
+    }
+
     pub fn serial(&self) -> &Serial {
         &self.serial
     }
@@ -56,11 +136,140 @@ impl Bus {
         &self.timer
     }
 
+    pub fn timer_mut(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+
     pub fn interrupts(&self) -> &Interrupts {
         &self.interrupts
     }
 
+    pub fn interrupts_mut(&mut self) -> &mut Interrupts {
+        &mut self.interrupts
+    }
+
+    /// The values of 0xFF00-0xFF7F, read without any side effects.
+    pub fn io_registers(&self) -> [u8; 0x80] {
+        let mut registers = [0xFF; 0x80];
+
+        registers[0x00] = self.joypad.joyp().into();
+        registers[0x01] = self.serial.sb();
+        registers[0x02] = self.serial.sc().into();
+        registers[0x04] = self.timer.div();
+        registers[0x05] = self.timer.tima();
+        registers[0x06] = self.timer.tma();
+        registers[0x07] = self.timer.tac().into();
+        registers[0x0F] = self.interrupts.intf().into();
+        registers[0x10..0x40].copy_from_slice(&self.apu.registers());
+        registers[0x40] = self.ppu.lcdc().into();
+        registers[0x41] = self.ppu.stat().into();
+        registers[0x42] = self.ppu.scy();
+        registers[0x43] = self.ppu.scx();
+        registers[0x44] = self.ppu.ly();
+        registers[0x45] = self.ppu.lyc();
+        registers[0x46] = self.dma.source();
+        registers[0x47] = self.ppu.bgp().into();
+        registers[0x48] = self.ppu.obp0().into();
+        registers[0x49] = self.ppu.obp1().into();
+        registers[0x4A] = self.ppu.wy();
+        registers[0x4B] = self.ppu.wx();
+        registers[0x50] = if self.boot_rom.is_some() { 0x00 } else { 0x01 };
+
+        registers
+    }
+
+    /// Restores 0xFF00-0xFF7F without the side effects of writing to them.
+    pub fn restore_io_registers(&mut self, registers: &[u8; 0x80]) {
+        self.joypad.set_joyp(registers[0x00]);
+        self.serial.set_sb(registers[0x01]);
+        self.serial.set_sc(registers[0x02]);
+        self.timer.restore_registers(
+            registers[0x04],
+            registers[0x05],
+            registers[0x06],
+            registers[0x07],
+        );
+        self.interrupts.set_intf(registers[0x0F]);
+        self.apu.restore_registers(&registers[0x10..0x40]);
+        self.ppu.restore_registers(&registers[0x40..=0x4B]);
+
+        // Only the register is known, so any transfer that's running is cancelled
+        self.dma = DMA::new();
+        self.dma.set_source(registers[0x46]);
+
+        // The boot ROM can't be mapped back in once it's gone
+        if registers[0x50] != 0 {
+            self.boot_rom = None;
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.boot_rom.is_some());
+
+        if let Some(boot_rom) = &self.boot_rom {
+            state.write_bytes(boot_rom);
+        }
+
+        self.cartridge.save_state(state);
+        self.ppu.save_state(state);
+        state.write_bytes(&self.wram);
+        self.joypad.save_state(state);
+        self.serial.save_state(state);
+        self.timer.save_state(state);
+        self.interrupts.save_state(state);
+        state.write_bytes(&self.hram);
+        self.dma.save_state(state);
+        self.apu.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.boot_rom = if state.read_bool()? {
+            let boot_rom = state.read_bytes()?;
+
+            if boot_rom.len() != BOOT_ROM_SIZE {
+                return Err(StateError::InvalidValue("boot ROM"));
+            }
+
+            Some(boot_rom.to_vec())
+        } else {
+            None
+        };
+
+        let rumble = self.cartridge.rumble();
+        self.cartridge.load_state(state)?;
+
+        if self.cartridge.rumble() != rumble {
+            self.hal.borrow_mut().set_rumble(self.cartridge.rumble());
+        }
+
+        self.ppu.load_state(state)?;
+        state.read_bytes_into(&mut self.wram, "WRAM")?;
+        self.joypad.load_state(state)?;
+        self.serial.load_state(state)?;
+        self.timer.load_state(state)?;
+        self.interrupts.load_state(state)?;
+        state.read_bytes_into(&mut self.hram, "HRAM")?;
+
+        if state.version() >= 7 {
+            self.dma.load_state(state)?;
+        } else {
+            self.dma = DMA::new();
+        }
+
+        if state.version() >= 8 {
+            self.apu.load_state(state)?;
+        } else {
+            self.apu.reset();
+        }
+
+        Ok(())
+    }
+
     fn read(&mut self, addr: u16) -> u8 {
+        if let (0x0000..=0x00FF, Some(boot_rom)) = (addr, &self.boot_rom) {
+            return boot_rom[usize::from(addr)];
+        }
+
         match addr {
             0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.read(addr),
             0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.read(addr),
@@ -87,7 +296,7 @@ impl Bus {
             0xFF43 => self.ppu.scx(),
             0xFF44 => self.ppu.ly(),
             0xFF45 => self.ppu.lyc(),
-            // 0xFF46 DMA
+            0xFF46 => self.dma.source(),
             0xFF47 => self.ppu.bgp().into(),
             0xFF48 => self.ppu.obp0().into(),
             0xFF49 => self.ppu.obp1().into(),
@@ -96,6 +305,8 @@ impl Bus {
 
             0xFF0F => self.interrupts.intf().into(),
 
+            0xFF10..=0xFF3F => self.apu.read(addr),
+
             0xFF80..=0xFFFE => {
                 let offset = usize::from(addr) - 0xFF80;
                 self.hram[offset]
@@ -108,7 +319,14 @@ impl Bus {
     }
 
     fn tick_m_cycle_except_timer(&mut self) {
-        // TODO: oam?
+        if let Some((src, offset)) = self.dma.tick_m_cycle() {
+            let value = self.read(src);
+
+            self.dma.set_bus_value(value);
+            self.ppu.oam_mut()[offset] = value;
+        }
+
+        let frames = self.ppu.frames();
 
         for _ in 0..4 {
             let (vblank, lcd_stat) = self.ppu.tick();
@@ -122,7 +340,14 @@ impl Bus {
             }
         }
 
-        // TODO: apu
+        self.apu.tick_m_cycle(self.timer.frame_sequencer_bit());
+
+        if self.ppu.frames() != frames {
+            self.apu.end_frame();
+            self.hal.borrow_mut().audio_ready(self.apu.audio_buffer());
+        }
+
+        self.cartridge.tick_m_cycle();
 
         if self.serial.tick_m_cycle() {
             self.interrupts.trigger_interrupt(Interrupt::Serial);
@@ -155,9 +380,20 @@ impl Bus {
         }
     }
 
-    fn write(&mut self, addr: u16, value: u8) {
+    pub fn write_cartridge(&mut self, addr: u16, value: u8) {
+        let rumble = self.cartridge.rumble();
+        self.cartridge.write(addr, value);
+
+        if self.cartridge.rumble() != rumble {
+            self.hal.borrow_mut().set_rumble(self.cartridge.rumble());
+        }
+    }
+
+    /// Writes without ticking the rest of the machine. The timer registers
+    /// aren't handled here, use `timer_mut().restore_registers` for them.
+    pub(crate) fn write(&mut self, addr: u16, value: u8) {
         match addr {
-            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.write(addr, value),
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.write_cartridge(addr, value),
             0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.write(addr, value),
 
             0xC000..=0xFDFF => {
@@ -175,29 +411,30 @@ impl Bus {
 
             0xFF0F => self.interrupts.set_intf(value),
 
+            0xFF10..=0xFF3F => self.apu.write(addr, value),
+
             0xFF40 => self.ppu.set_lcdc(value),
-            0xFF41 => self.ppu.set_stat(value),
+            0xFF41 => {
+                let should_interrupt = self.ppu.set_stat(value);
+
+                if should_interrupt {
+                    self.interrupts.trigger_interrupt(Interrupt::LCDStat);
+                }
+            }
             0xFF42 => self.ppu.set_scy(value),
             0xFF43 => self.ppu.set_scx(value),
             0xFF44 => self.ppu.set_ly(value),
             0xFF45 => self.ppu.set_lyc(value),
-            0xFF46 => {
-                let src = u16::from_le_bytes([0, value]);
-                let data: [u8; 160] = (0..160)
-                    .map(|offset| self.read(src + offset as u16))
-                    .collect::<Vec<_>>()
-                    .as_slice()
-                    .try_into()
-                    .unwrap();
-
-                self.ppu.dma(data);
-            }
+            0xFF46 => self.dma.start(value),
             0xFF47 => self.ppu.set_bgp(value),
             0xFF48 => self.ppu.set_obp0(value),
             0xFF49 => self.ppu.set_obp1(value),
             0xFF4A => self.ppu.set_wy(value),
             0xFF4B => self.ppu.set_wx(value),
 
+            // Writing a non-zero value unmaps the boot ROM until the next reset
+            0xFF50 if value != 0 => self.boot_rom = None,
+
             0xFF80..=0xFFFE => {
                 let offset = usize::from(addr) - 0xFF80;
                 self.hram[offset] = value;
@@ -220,6 +457,17 @@ impl super::cpu::Bus for Bus {
 
     fn read_m_cycle(&mut self, addr: u16) -> u8 {
         self.tick_m_cycle();
+
+        // OAM DMA has the bus apart from HRAM and the IO registers, and OAM
+        // can't be read at all while it's being written to
+        if self.dma.is_active() {
+            match addr {
+                0xFE00..=0xFEFF => return 0xFF,
+                0x0000..=0xFDFF => return self.dma.bus_value(),
+                _ => {}
+            }
+        }
+
         self.read(addr)
     }
 
@@ -235,6 +483,67 @@ impl super::cpu::Bus for Bus {
         }
 
         self.tick_m_cycle();
+
+        if self.dma.is_active() && addr < 0xFF00 {
+            return;
+        }
+
         self.write(addr, value);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::cpu::Bus as _;
+    use super::*;
+    use crate::test_utils::NullHAL;
+    use crate::ROM;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn it_should_only_give_the_cpu_hram_during_oam_dma() {
+        let cartridge = Cartridge::try_from(ROM::from(vec![0; 0x8000])).unwrap();
+        let mut bus = Bus::with_cartridge(cartridge, Rc::new(RefCell::new(NullHAL)));
+
+        for (offset, byte) in bus.wram_mut()[..160].iter_mut().enumerate() {
+            *byte = offset as u8;
+        }
+        bus.hram_mut()[0] = 0x42;
+
+        bus.write_m_cycle(0xFF46, 0xC0);
+        bus.tick_m_cycle();
+
+        // Each read sees the byte copied on the same M-cycle
+        assert_eq!(0x00, bus.read_m_cycle(0xC080));
+        assert_eq!(0xFF, bus.read_m_cycle(0xFE00));
+        assert_eq!(0x42, bus.read_m_cycle(0xFF80));
+
+        bus.write_m_cycle(0xC000, 0xAA);
+
+        for _ in 0..155 {
+            bus.tick_m_cycle();
+        }
+
+        assert!(bus.dma.is_active());
+        bus.tick_m_cycle();
+
+        assert_eq!(0x00, bus.read_m_cycle(0xC000));
+        assert_eq!(bus.wram()[..160], bus.ppu().oam()[..]);
+    }
+
+    #[test]
+    fn it_should_cancel_oam_dma_when_restoring_the_io_registers() {
+        let cartridge = Cartridge::try_from(ROM::from(vec![0; 0x8000])).unwrap();
+        let mut bus = Bus::with_cartridge(cartridge, Rc::new(RefCell::new(NullHAL)));
+
+        bus.write_m_cycle(0xFF46, 0xC0);
+        bus.tick_m_cycle();
+        assert!(bus.dma.is_active());
+
+        let registers = bus.io_registers();
+        bus.restore_io_registers(&registers);
+
+        assert!(!bus.dma.is_active());
+        assert_eq!(0xC0, bus.io_registers()[0x46]);
+    }
+}
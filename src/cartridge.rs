@@ -1,14 +1,30 @@
 mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod ram;
 mod rom_only;
+mod rtc;
 
 use mbc1::MBC1;
+use mbc2::MBC2;
+use mbc3::MBC3;
+use mbc5::MBC5;
+use ram::RAM;
 use rom_only::ROMOnly;
+use rtc::RTC;
 
+use alloc::vec::Vec;
+
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::ROM;
 
 pub enum Cartridge {
     ROMOnly(ROMOnly),
     MBC1(MBC1),
+    MBC2(MBC2),
+    MBC3(MBC3),
+    MBC5(MBC5),
 }
 
 impl Cartridge {
@@ -19,6 +35,18 @@ impl Cartridge {
     pub fn mbc1(rom: ROM, ram_size: usize) -> Self {
         Cartridge::MBC1(MBC1::new(rom, ram_size))
     }
+
+    pub fn mbc2(rom: ROM) -> Self {
+        Cartridge::MBC2(MBC2::new(rom))
+    }
+
+    pub fn mbc3(rom: ROM, ram_size: usize, has_rtc: bool) -> Self {
+        Cartridge::MBC3(MBC3::new(rom, ram_size, has_rtc))
+    }
+
+    pub fn mbc5(rom: ROM, ram_size: usize, has_rumble: bool) -> Self {
+        Cartridge::MBC5(MBC5::new(rom, ram_size, has_rumble))
+    }
 }
 
 impl Cartridge {
@@ -26,6 +54,9 @@ impl Cartridge {
         match self {
             Cartridge::ROMOnly(rom) => rom.rom(),
             Cartridge::MBC1(mbc1) => mbc1.rom(),
+            Cartridge::MBC2(mbc2) => mbc2.rom(),
+            Cartridge::MBC3(mbc3) => mbc3.rom(),
+            Cartridge::MBC5(mbc5) => mbc5.rom(),
         }
     }
 
@@ -33,6 +64,9 @@ impl Cartridge {
         match self {
             Cartridge::ROMOnly(rom) => rom.read(addr),
             Cartridge::MBC1(mbc1) => mbc1.read(addr),
+            Cartridge::MBC2(mbc2) => mbc2.read(addr),
+            Cartridge::MBC3(mbc3) => mbc3.read(addr),
+            Cartridge::MBC5(mbc5) => mbc5.read(addr),
         }
     }
 
@@ -40,6 +74,206 @@ impl Cartridge {
         match self {
             Cartridge::ROMOnly(rom) => rom.write(addr, value),
             Cartridge::MBC1(mbc1) => mbc1.write(addr, value),
+            Cartridge::MBC2(mbc2) => mbc2.write(addr, value),
+            Cartridge::MBC3(mbc3) => mbc3.write(addr, value),
+            Cartridge::MBC5(mbc5) => mbc5.write(addr, value),
+        }
+    }
+
+    fn ram(&self) -> Option<&RAM> {
+        match self {
+            Cartridge::ROMOnly(_) => None,
+            Cartridge::MBC1(mbc1) => Some(mbc1.ram()),
+            Cartridge::MBC2(mbc2) => Some(mbc2.ram()),
+            Cartridge::MBC3(mbc3) => Some(mbc3.ram()),
+            Cartridge::MBC5(mbc5) => Some(mbc5.ram()),
+        }
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut RAM> {
+        match self {
+            Cartridge::ROMOnly(_) => None,
+            Cartridge::MBC1(mbc1) => Some(mbc1.ram_mut()),
+            Cartridge::MBC2(mbc2) => Some(mbc2.ram_mut()),
+            Cartridge::MBC3(mbc3) => Some(mbc3.ram_mut()),
+            Cartridge::MBC5(mbc5) => Some(mbc5.ram_mut()),
+        }
+    }
+
+    pub fn ram_data(&self) -> &[u8] {
+        self.ram().map(RAM::data).unwrap_or(&[])
+    }
+
+    pub fn restore_ram(&mut self, data: &[u8]) {
+        if let Some(ram) = self.ram_mut() {
+            ram.restore(data);
+        }
+    }
+
+    pub fn rtc(&self) -> Option<&RTC> {
+        match self {
+            Cartridge::MBC3(mbc3) => mbc3.rtc(),
+            _ => None,
+        }
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut RTC> {
+        match self {
+            Cartridge::MBC3(mbc3) => mbc3.rtc_mut(),
+            _ => None,
+        }
+    }
+
+    /// The register writes that put a freshly reset mapper into its current state.
+    pub fn register_writes(&self) -> Vec<(u16, u8)> {
+        match self {
+            Cartridge::ROMOnly(_) => Vec::new(),
+            Cartridge::MBC1(mbc1) => mbc1.register_writes(),
+            Cartridge::MBC2(mbc2) => mbc2.register_writes(),
+            Cartridge::MBC3(mbc3) => mbc3.register_writes(),
+            Cartridge::MBC5(mbc5) => mbc5.register_writes(),
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.rom().has_battery()
+    }
+
+    /// Exports the external RAM in the `.sav` layout used by other emulators,
+    /// with the RTC footer appended for MBC3 carts that have a timer, and marks
+    /// it as saved. `now` is the current UNIX time in seconds.
+    pub fn save_ram(&mut self, now: u64) -> Vec<u8> {
+        let mut data = self
+            .ram()
+            .map(|ram| ram.data().to_vec())
+            .unwrap_or_default();
+
+        if let Some(rtc) = self.rtc() {
+            data.extend(rtc.save_footer(now));
+        }
+
+        self.clear_save_ram_dirty();
+
+        data
+    }
+
+    /// Imports external RAM previously exported by `save_ram` or another emulator.
+    /// The RTC catches up with the time since it was saved, up to `now`.
+    pub fn load_save_ram(&mut self, data: &[u8], now: u64) {
+        let ram_len = self.ram().map(|ram| ram.data().len()).unwrap_or(0);
+        let (ram, footer) = data.split_at(ram_len.min(data.len()));
+
+        if let Some(ram_mut) = self.ram_mut() {
+            ram_mut.load(ram);
+        }
+
+        if let Some(rtc) = self.rtc_mut() {
+            rtc.load_save_footer(footer, now);
+        }
+    }
+
+    /// Returns true if the external RAM has changed since it was last saved, loaded or cleared.
+    pub fn save_ram_dirty(&self) -> bool {
+        self.ram().map(RAM::dirty).unwrap_or(false)
+    }
+
+    pub fn clear_save_ram_dirty(&mut self) {
+        if let Some(ram) = self.ram_mut() {
+            ram.clear_dirty();
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        match self {
+            Cartridge::ROMOnly(_) => {}
+            Cartridge::MBC1(mbc1) => mbc1.save_state(state),
+            Cartridge::MBC2(mbc2) => mbc2.save_state(state),
+            Cartridge::MBC3(mbc3) => mbc3.save_state(state),
+            Cartridge::MBC5(mbc5) => mbc5.save_state(state),
+        }
+    }
+
+    /// Loads the mapper state, the state must have been saved from the same ROM.
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        match self {
+            Cartridge::ROMOnly(_) => Ok(()),
+            Cartridge::MBC1(mbc1) => mbc1.load_state(state),
+            Cartridge::MBC2(mbc2) => mbc2.load_state(state),
+            Cartridge::MBC3(mbc3) => mbc3.load_state(state),
+            Cartridge::MBC5(mbc5) => mbc5.load_state(state),
+        }
+    }
+
+    pub fn rumble(&self) -> bool {
+        match self {
+            Cartridge::MBC5(mbc5) => mbc5.rumble(),
+            _ => false,
         }
     }
+
+    pub fn tick_m_cycle(&mut self) {
+        if let Cartridge::MBC3(mbc3) = self {
+            mbc3.tick_m_cycle();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // MBC3+TIMER+RAM+BATTERY with 8 KiB of RAM
+    fn mbc3() -> Cartridge {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x10;
+        rom[0x149] = 0x02;
+
+        let mut cartridge = Cartridge::mbc3(ROM::from(rom), 0x2000, true);
+        cartridge.write(0x0000, 0x0A);
+        cartridge
+    }
+
+    #[test]
+    fn it_should_round_trip_battery_ram_and_the_rtc() {
+        let mut cartridge = mbc3();
+        cartridge.write(0xA000, 0x12);
+        cartridge.write(0xBFFF, 0x34);
+        cartridge.write(0x4000, 0x0A);
+        cartridge.write(0xA000, 7);
+
+        let data = cartridge.save_ram(1_000_000);
+        assert_eq!(0x2000 + 48, data.len());
+
+        // Loaded a minute and a half later
+        let mut loaded = mbc3();
+        loaded.load_save_ram(&data, 1_000_000 + 90);
+
+        assert_eq!(0x12, loaded.read(0xA000));
+        assert_eq!(0x34, loaded.read(0xBFFF));
+
+        loaded.write(0x6000, 0x00);
+        loaded.write(0x6000, 0x01);
+
+        for &(register, value) in &[(0x08, 30), (0x09, 1), (0x0A, 7)] {
+            loaded.write(0x4000, register);
+            assert_eq!(value, loaded.read(0xA000));
+        }
+    }
+
+    #[test]
+    fn it_should_be_dirty_until_saved() {
+        let mut cartridge = mbc3();
+        assert!(!cartridge.save_ram_dirty());
+
+        cartridge.write(0xA000, 0x12);
+        assert!(cartridge.save_ram_dirty());
+
+        cartridge.save_ram(0);
+        assert!(!cartridge.save_ram_dirty());
+
+        // The RTC registers aren't part of the RAM
+        cartridge.write(0x4000, 0x08);
+        cartridge.write(0xA000, 0x12);
+        assert!(!cartridge.save_ram_dirty());
+    }
 }
@@ -1,6 +1,8 @@
-use alloc::vec::{self, Vec};
-use bitfield::BitRange;
+use alloc::vec::Vec;
+use bitfield::{Bit, BitRange};
 
+use super::ram::RAM;
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::ROM;
 
 enum BankMode {
@@ -11,7 +13,7 @@ enum BankMode {
 pub struct MBC1 {
     rom: ROM,
     rom_bank: u8,
-    ram: Vec<u8>,
+    ram: RAM,
     ram_bank: u8,
     ram_enabled: bool,
     bank_mode: BankMode,
@@ -22,7 +24,7 @@ impl MBC1 {
         MBC1 {
             rom,
             rom_bank: 1,
-            ram: vec::from_elem(0, ram_size),
+            ram: RAM::new(ram_size),
             ram_bank: 0,
             ram_enabled: false,
             bank_mode: BankMode::ROM,
@@ -33,6 +35,57 @@ impl MBC1 {
         &self.rom
     }
 
+    pub fn ram(&self) -> &RAM {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut RAM {
+        &mut self.ram
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        self.ram.save_state(state);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.ram_enabled);
+        state.write_u8(match self.bank_mode {
+            BankMode::ROM => 0x00,
+            BankMode::RAM => 0x01,
+        });
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = state.read_u8()? & 0x7F;
+        self.ram.load_state(state)?;
+        self.ram_bank = state.read_u8()? & 0x03;
+        self.ram_enabled = state.read_bool()?;
+        self.bank_mode = match state.read_u8()? {
+            0x00 => BankMode::ROM,
+            0x01 => BankMode::RAM,
+            _ => return Err(StateError::InvalidValue("MBC1 banking mode")),
+        };
+
+        Ok(())
+    }
+
+    /// The register writes that put a freshly reset mapper into its current state.
+    pub fn register_writes(&self) -> Vec<(u16, u8)> {
+        let bank_mode = match self.bank_mode {
+            BankMode::ROM => 0x00,
+            BankMode::RAM => 0x01,
+        };
+
+        vec![
+            (0x6000, 0x01),
+            (0x4000, self.ram_bank),
+            (0x6000, 0x00),
+            (0x4000, self.rom_bank >> 5),
+            (0x2000, self.rom_bank & 0x1F),
+            (0x6000, bank_mode),
+            (0x0000, if self.ram_enabled { 0x0A } else { 0x00 }),
+        ]
+    }
+
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
             // ROM Bank 00 (Read Only)
@@ -58,7 +111,7 @@ impl MBC1 {
                 }
 
                 let offset = usize::from(self.ram_bank) * 0x2000;
-                self.ram[usize::from(addr) - 0xA000 + offset]
+                self.ram.read(usize::from(addr) - 0xA000 + offset)
             }
             _ => unreachable!(),
         }
@@ -79,24 +132,23 @@ impl MBC1 {
                 BankMode::ROM => {
                     self.rom_bank.set_bit_range(6, 5, value);
                 }
-                BankMode::RAM => match value {
-                    0x00..=0x03 => self.ram_bank = value,
-                    _ => unreachable!(),
-                },
+                BankMode::RAM => {
+                    self.ram_bank = BitRange::<u8>::bit_range(&value, 1, 0);
+                }
             },
             // ROM/RAM Mode Select (Write Only)
             0x6000..=0x7FFF => {
-                self.bank_mode = match value {
-                    0x00 => BankMode::ROM,
-                    0x01 => BankMode::RAM,
-                    _ => unreachable!(),
+                self.bank_mode = if value.bit(0) {
+                    BankMode::RAM
+                } else {
+                    BankMode::ROM
                 }
             }
             // RAM Bank 00-03, if any (Read/Write)
             0xA000..=0xBFFF => {
-                if self.ram.len() > 0 && self.ram_enabled {
+                if self.ram_enabled {
                     let offset = usize::from(self.ram_bank) * 0x2000;
-                    self.ram[usize::from(addr) - 0xA000 + offset] = value;
+                    self.ram.write(usize::from(addr) - 0xA000 + offset, value);
                 }
             }
             _ => unreachable!(),
@@ -0,0 +1,176 @@
+use alloc::vec::Vec;
+use bitfield::{Bit, BitRange};
+
+use super::ram::RAM;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::ROM;
+
+pub struct MBC2 {
+    rom: ROM,
+    rom_bank: u8,
+    ram: RAM,
+    ram_enabled: bool,
+}
+
+impl MBC2 {
+    pub fn new(rom: ROM) -> Self {
+        MBC2 {
+            rom,
+            rom_bank: 1,
+            ram: RAM::new(512),
+            ram_enabled: false,
+        }
+    }
+
+    pub fn rom(&self) -> &ROM {
+        &self.rom
+    }
+
+    pub fn ram(&self) -> &RAM {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut RAM {
+        &mut self.ram
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        self.ram.save_state(state);
+        state.write_bool(self.ram_enabled);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = state.read_u8()? & 0x0F;
+        self.ram.load_state(state)?;
+        self.ram_enabled = state.read_bool()?;
+
+        Ok(())
+    }
+
+    /// The register writes that put a freshly reset mapper into its current state.
+    pub fn register_writes(&self) -> Vec<(u16, u8)> {
+        vec![
+            (0x0000, if self.ram_enabled { 0x0A } else { 0x00 }),
+            (0x0100, self.rom_bank),
+        ]
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            // ROM Bank 00 (Read Only)
+            0x0000..=0x3FFF => self.rom.read(addr),
+            // ROM Bank 01-0F (Read Only)
+            0x4000..=0x7FFF => self.rom.read_bank(usize::from(self.rom_bank), addr),
+            // 512x4bits RAM, built-in into the MBC2 chip (Read/Write)
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return 0xFF;
+                }
+
+                // Only the lower 9 bits of the address are decoded, and
+                // the upper 4 bits of each byte are not connected
+                self.ram.read(usize::from(addr & 0x01FF)) | 0xF0
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            // RAM Enable - or - ROM Bank Number (Write Only)
+            0x0000..=0x3FFF => {
+                // The least significant bit of the upper address byte selects the register
+                if !addr.bit(8) {
+                    self.ram_enabled = BitRange::<u8>::bit_range(&value, 3, 0) == 0x0A;
+                } else {
+                    self.rom_bank = match BitRange::<u8>::bit_range(&value, 3, 0) {
+                        0x00 => 0x01,
+                        bank => bank,
+                    };
+                }
+            }
+            0x4000..=0x7FFF => {}
+            // 512x4bits RAM, built-in into the MBC2 chip (Read/Write)
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    self.ram.write(usize::from(addr & 0x01FF), value & 0x0F);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each bank starts with its own number
+    fn rom(banks: usize) -> ROM {
+        let mut rom = vec![0; banks * 0x4000];
+
+        for bank in 0..banks {
+            rom[bank * 0x4000] = bank as u8;
+        }
+
+        ROM::from(rom)
+    }
+
+    #[test]
+    fn it_should_select_the_register_with_address_bit_8() {
+        let mut mbc2 = MBC2::new(rom(16));
+
+        // Bit 8 clear is RAM enable, whatever the rest of the address
+        mbc2.write(0x2000, 0x0A);
+        assert_eq!(0x01, mbc2.read(0x4000));
+        assert_eq!(0xF0, mbc2.read(0xA000));
+
+        mbc2.write(0x0000, 0x00);
+        assert_eq!(0xFF, mbc2.read(0xA000));
+
+        // Bit 8 set is the ROM bank, even below 0x2000
+        mbc2.write(0x0100, 0x05);
+        assert_eq!(0x05, mbc2.read(0x4000));
+        assert_eq!(0xFF, mbc2.read(0xA000));
+
+        mbc2.write(0x3FFF, 0xFE);
+        assert_eq!(0x0E, mbc2.read(0x4000));
+    }
+
+    #[test]
+    fn it_should_map_rom_bank_0_to_1() {
+        let mut mbc2 = MBC2::new(rom(16));
+        mbc2.write(0x2100, 0x00);
+
+        assert_eq!(0x01, mbc2.read(0x4000));
+
+        mbc2.write(0x2100, 0x10);
+        assert_eq!(0x01, mbc2.read(0x4000));
+    }
+
+    #[test]
+    fn it_should_only_store_the_lower_nibble() {
+        let mut mbc2 = MBC2::new(rom(16));
+        mbc2.write(0x0000, 0x0A);
+        mbc2.write(0xA000, 0x5A);
+
+        assert_eq!(0xFA, mbc2.read(0xA000));
+    }
+
+    #[test]
+    fn it_should_mirror_the_ram() {
+        let mut mbc2 = MBC2::new(rom(16));
+        mbc2.write(0x0000, 0x0A);
+        mbc2.write(0xA000, 0x01);
+        mbc2.write(0xA1FF, 0x02);
+
+        for mirror in (0xA000..=0xBFFF).step_by(0x200) {
+            assert_eq!(0xF1, mbc2.read(mirror));
+            assert_eq!(0xF2, mbc2.read(mirror + 0x1FF));
+        }
+
+        mbc2.write(0xBE00, 0x03);
+        assert_eq!(0xF3, mbc2.read(0xA000));
+    }
+}
@@ -0,0 +1,286 @@
+use alloc::vec::Vec;
+use bitfield::BitRange;
+
+use super::ram::RAM;
+use super::rtc::RTC;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::ROM;
+
+pub struct MBC3 {
+    rom: ROM,
+    rom_bank: u8,
+    ram: RAM,
+    ram_bank: u8,
+    ram_and_timer_enabled: bool,
+    rtc: Option<RTC>,
+}
+
+impl MBC3 {
+    pub fn new(rom: ROM, ram_size: usize, has_rtc: bool) -> Self {
+        MBC3 {
+            rom,
+            rom_bank: 1,
+            ram: RAM::new(ram_size),
+            ram_bank: 0,
+            ram_and_timer_enabled: false,
+            rtc: if has_rtc { Some(RTC::new()) } else { None },
+        }
+    }
+
+    pub fn rom(&self) -> &ROM {
+        &self.rom
+    }
+
+    pub fn ram(&self) -> &RAM {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut RAM {
+        &mut self.ram
+    }
+
+    pub fn rtc(&self) -> Option<&RTC> {
+        self.rtc.as_ref()
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut RTC> {
+        self.rtc.as_mut()
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        self.ram.save_state(state);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.ram_and_timer_enabled);
+        state.write_bool(self.rtc.is_some());
+
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(state);
+        }
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = state.read_u8()? & 0x7F;
+        self.ram.load_state(state)?;
+        self.ram_bank = state.read_u8()?;
+        self.ram_and_timer_enabled = state.read_bool()?;
+
+        match (state.read_bool()?, &mut self.rtc) {
+            (true, Some(rtc)) => rtc.load_state(state),
+            (false, None) => Ok(()),
+            _ => Err(StateError::InvalidValue("RTC")),
+        }
+    }
+
+    /// The register writes that put a freshly reset mapper into its current state.
+    pub fn register_writes(&self) -> Vec<(u16, u8)> {
+        vec![
+            (
+                0x0000,
+                if self.ram_and_timer_enabled {
+                    0x0A
+                } else {
+                    0x00
+                },
+            ),
+            (0x2000, self.rom_bank),
+            (0x4000, self.ram_bank),
+        ]
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            // ROM Bank 00 (Read Only)
+            0x0000..=0x3FFF => self.rom.read(addr),
+            // ROM Bank 01-7F (Read Only)
+            0x4000..=0x7FFF => {
+                let rom_bank = match self.rom_bank {
+                    0x00 => 0x01,
+                    bank => bank,
+                };
+
+                self.rom.read_bank(usize::from(rom_bank), addr)
+            }
+            // RAM Bank 00-03, if any (Read/Write) - or - RTC Register 08-0C (Read/Write)
+            0xA000..=0xBFFF => {
+                if !self.ram_and_timer_enabled {
+                    return 0xFF;
+                }
+
+                match (self.ram_bank, &self.rtc) {
+                    (0x00..=0x07, _) => {
+                        let offset = usize::from(self.ram_bank) * 0x2000;
+                        self.ram.read(usize::from(addr) - 0xA000 + offset)
+                    }
+                    (0x08..=0x0C, Some(rtc)) => rtc.read(self.ram_bank),
+                    _ => 0xFF,
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            // RAM and Timer Enable (Write Only)
+            0x0000..=0x1FFF => {
+                self.ram_and_timer_enabled = BitRange::<u8>::bit_range(&value, 3, 0) == 0x0A;
+            }
+            // ROM Bank Number (Write Only)
+            0x2000..=0x3FFF => {
+                self.rom_bank = BitRange::<u8>::bit_range(&value, 6, 0);
+            }
+            // RAM Bank Number - or - RTC Register Select (Write Only)
+            0x4000..=0x5FFF => {
+                self.ram_bank = value;
+            }
+            // Latch Clock Data (Write Only)
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(value);
+                }
+            }
+            // RAM Bank 00-03, if any (Read/Write) - or - RTC Register 08-0C (Read/Write)
+            0xA000..=0xBFFF => {
+                if !self.ram_and_timer_enabled {
+                    return;
+                }
+
+                match (self.ram_bank, &mut self.rtc) {
+                    (0x00..=0x07, _) => {
+                        let offset = usize::from(self.ram_bank) * 0x2000;
+                        self.ram.write(usize::from(addr) - 0xA000 + offset, value);
+                    }
+                    (0x08..=0x0C, Some(rtc)) => rtc.write(self.ram_bank, value),
+                    _ => {}
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn tick_m_cycle(&mut self) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick_m_cycle();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each bank starts with its own number
+    fn rom(banks: usize) -> ROM {
+        let mut rom = vec![0; banks * 0x4000];
+
+        for bank in 0..banks {
+            rom[bank * 0x4000] = bank as u8;
+        }
+
+        ROM::from(rom)
+    }
+
+    fn mbc3() -> MBC3 {
+        let mut mbc3 = MBC3::new(rom(0x80), 0x8000, true);
+        mbc3.write(0x0000, 0x0A);
+        mbc3
+    }
+
+    #[test]
+    fn it_should_select_7_bit_rom_banks() {
+        let mut mbc3 = mbc3();
+
+        assert_eq!(0x01, mbc3.read(0x4000));
+
+        mbc3.write(0x2000, 0x00);
+        assert_eq!(0x01, mbc3.read(0x4000));
+
+        mbc3.write(0x2000, 0x45);
+        assert_eq!(0x45, mbc3.read(0x4000));
+
+        mbc3.write(0x2000, 0xFF);
+        assert_eq!(0x7F, mbc3.read(0x4000));
+
+        assert_eq!(0x00, mbc3.read(0x0000));
+    }
+
+    #[test]
+    fn it_should_select_ram_banks_or_rtc_registers() {
+        let mut mbc3 = mbc3();
+
+        for bank in 0..4 {
+            mbc3.write(0x4000, bank);
+            mbc3.write(0xA000, 0x10 + bank);
+        }
+
+        mbc3.write(0x4000, 0x08);
+        mbc3.write(0xA000, 30);
+
+        for bank in 0..4 {
+            mbc3.write(0x4000, bank);
+            assert_eq!(0x10 + bank, mbc3.read(0xA000));
+        }
+
+        mbc3.write(0x6000, 0x00);
+        mbc3.write(0x6000, 0x01);
+        mbc3.write(0x4000, 0x08);
+        assert_eq!(30, mbc3.read(0xA000));
+    }
+
+    #[test]
+    fn it_should_read_and_write_the_rtc_registers() {
+        let mut mbc3 = mbc3();
+        let registers = [
+            (0x08, 59),
+            (0x09, 58),
+            (0x0A, 23),
+            (0x0B, 0xFF),
+            (0x0C, 0x41),
+        ];
+
+        for &(register, value) in &registers {
+            mbc3.write(0x4000, register);
+            mbc3.write(0xBFFF, value);
+        }
+
+        mbc3.write(0x6000, 0x00);
+        mbc3.write(0x6000, 0x01);
+
+        for &(register, value) in &registers {
+            mbc3.write(0x4000, register);
+            assert_eq!(value, mbc3.read(0xA000));
+        }
+    }
+
+    #[test]
+    fn it_should_latch_the_rtc_on_0_then_1() {
+        let mut mbc3 = mbc3();
+        mbc3.write(0x4000, 0x08);
+        mbc3.write(0xA000, 5);
+
+        assert_eq!(0, mbc3.read(0xA000));
+
+        mbc3.write(0x6000, 0x01);
+        assert_eq!(0, mbc3.read(0xA000));
+
+        mbc3.write(0x6000, 0x00);
+        mbc3.write(0x6000, 0x01);
+        assert_eq!(5, mbc3.read(0xA000));
+    }
+
+    #[test]
+    fn it_should_ignore_ram_and_rtc_until_enabled() {
+        let mut mbc3 = MBC3::new(rom(0x80), 0x8000, true);
+        mbc3.write(0xA000, 0x12);
+
+        assert_eq!(0xFF, mbc3.read(0xA000));
+
+        mbc3.write(0x0000, 0x0A);
+        assert_eq!(0x00, mbc3.read(0xA000));
+
+        mbc3.write(0x0000, 0x00);
+        mbc3.write(0x4000, 0x08);
+        assert_eq!(0xFF, mbc3.read(0xA000));
+    }
+}
@@ -0,0 +1,275 @@
+use alloc::vec::Vec;
+use bitfield::{Bit, BitRange};
+
+use super::ram::RAM;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::ROM;
+
+pub struct MBC5 {
+    rom: ROM,
+    rom_bank: u16,
+    ram: RAM,
+    ram_bank: u8,
+    ram_enabled: bool,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl MBC5 {
+    pub fn new(rom: ROM, ram_size: usize, has_rumble: bool) -> Self {
+        MBC5 {
+            rom,
+            rom_bank: 1,
+            ram: RAM::new(ram_size),
+            ram_bank: 0,
+            ram_enabled: false,
+            has_rumble,
+            rumble: false,
+        }
+    }
+
+    pub fn rom(&self) -> &ROM {
+        &self.rom
+    }
+
+    pub fn ram(&self) -> &RAM {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut RAM {
+        &mut self.ram
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.rom_bank);
+        self.ram.save_state(state);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.ram_enabled);
+        state.write_bool(self.rumble);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = state.read_u16()? & 0x01FF;
+        self.ram.load_state(state)?;
+        self.ram_bank = state.read_u8()? & 0x0F;
+        self.ram_enabled = state.read_bool()?;
+        self.rumble = state.read_bool()? && self.has_rumble;
+
+        Ok(())
+    }
+
+    /// The register writes that put a freshly reset mapper into its current state.
+    pub fn register_writes(&self) -> Vec<(u16, u8)> {
+        let [low, high] = self.rom_bank.to_le_bytes();
+        let ram_bank = if self.rumble {
+            self.ram_bank | 0x08
+        } else {
+            self.ram_bank
+        };
+
+        vec![
+            (0x0000, if self.ram_enabled { 0x0A } else { 0x00 }),
+            (0x2000, low),
+            (0x3000, high),
+            (0x4000, ram_bank),
+        ]
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            // ROM Bank 00 (Read Only)
+            0x0000..=0x3FFF => self.rom.read(addr),
+            // ROM Bank 000-1FF (Read Only)
+            0x4000..=0x7FFF => self.rom.read_bank(usize::from(self.rom_bank), addr),
+            // RAM Bank 00-0F, if any (Read/Write)
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return 0xFF;
+                }
+
+                let offset = usize::from(self.ram_bank) * 0x2000;
+                self.ram.read(usize::from(addr) - 0xA000 + offset)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            // RAM Enable (Write Only)
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value == 0x0A;
+            }
+            // Low 8 bits of ROM Bank Number (Write Only)
+            0x2000..=0x2FFF => {
+                self.rom_bank.set_bit_range(7, 0, value);
+            }
+            // High bit of ROM Bank Number (Write Only)
+            0x3000..=0x3FFF => {
+                self.rom_bank.set_bit(8, value.bit(0));
+            }
+            // RAM Bank Number (Write Only)
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    // Rumble carts wire bit 3 to the motor instead of the RAM
+                    self.rumble = value.bit(3);
+                    self.ram_bank = BitRange::<u8>::bit_range(&value, 2, 0);
+                } else {
+                    self.ram_bank = BitRange::<u8>::bit_range(&value, 3, 0);
+                }
+            }
+            0x6000..=0x7FFF => {}
+            // RAM Bank 00-0F, if any (Read/Write)
+            0xA000..=0xBFFF => {
+                if self.ram_enabled {
+                    let offset = usize::from(self.ram_bank) * 0x2000;
+                    self.ram.write(usize::from(addr) - 0xA000 + offset, value);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cartridge::Cartridge;
+    use crate::hal::{Joypad, HAL};
+
+    // Each bank starts with the low and high bytes of its own number
+    fn rom(banks: usize) -> ROM {
+        let mut rom = vec![0; banks * 0x4000];
+
+        for bank in 0..banks {
+            let [low, high] = (bank as u16).to_le_bytes();
+            rom[bank * 0x4000] = low;
+            rom[bank * 0x4000 + 1] = high;
+        }
+
+        ROM::from(rom)
+    }
+
+    fn rom_bank(mbc5: &MBC5) -> u16 {
+        u16::from_le_bytes([mbc5.read(0x4000), mbc5.read(0x4001)])
+    }
+
+    #[test]
+    fn it_should_select_9_bit_rom_banks() {
+        let mut mbc5 = MBC5::new(rom(0x200), 0, false);
+
+        assert_eq!(0x001, rom_bank(&mbc5));
+
+        mbc5.write(0x2000, 0x45);
+        assert_eq!(0x045, rom_bank(&mbc5));
+
+        mbc5.write(0x3000, 0x01);
+        assert_eq!(0x145, rom_bank(&mbc5));
+
+        mbc5.write(0x2FFF, 0xFF);
+        assert_eq!(0x1FF, rom_bank(&mbc5));
+
+        mbc5.write(0x3FFF, 0x00);
+        assert_eq!(0x0FF, rom_bank(&mbc5));
+    }
+
+    #[test]
+    fn it_should_select_rom_bank_0() {
+        let mut mbc5 = MBC5::new(rom(0x200), 0, false);
+        mbc5.write(0x2000, 0x00);
+
+        // Unlike MBC1, bank 0 isn't turned into bank 1
+        assert_eq!(0x000, rom_bank(&mbc5));
+    }
+
+    #[test]
+    fn it_should_select_16_ram_banks() {
+        let mut mbc5 = MBC5::new(rom(2), 0x20000, false);
+        mbc5.write(0x0000, 0x0A);
+
+        for bank in 0..16 {
+            mbc5.write(0x4000, bank);
+            mbc5.write(0xA000, 0x10 + bank);
+        }
+
+        for bank in 0..16 {
+            mbc5.write(0x4000, bank);
+            assert_eq!(0x10 + bank, mbc5.read(0xA000));
+        }
+    }
+
+    #[test]
+    fn it_should_ignore_ram_until_enabled() {
+        let mut mbc5 = MBC5::new(rom(2), 0x2000, false);
+        mbc5.write(0xA000, 0x12);
+
+        assert_eq!(0xFF, mbc5.read(0xA000));
+
+        mbc5.write(0x0000, 0x0A);
+        assert_eq!(0x00, mbc5.read(0xA000));
+
+        mbc5.write(0xA000, 0x12);
+        mbc5.write(0x0000, 0x00);
+        assert_eq!(0xFF, mbc5.read(0xA000));
+
+        mbc5.write(0x0000, 0x0A);
+        assert_eq!(0x12, mbc5.read(0xA000));
+    }
+
+    #[test]
+    fn it_should_mask_the_rumble_bit_out_of_the_ram_bank() {
+        let mut mbc5 = MBC5::new(rom(2), 0x20000, true);
+        mbc5.write(0x0000, 0x0A);
+
+        mbc5.write(0x4000, 0x01);
+        mbc5.write(0xA000, 0x12);
+
+        mbc5.write(0x4000, 0x09);
+        assert!(mbc5.rumble());
+        assert_eq!(0x12, mbc5.read(0xA000));
+
+        mbc5.write(0x4000, 0x01);
+        assert!(!mbc5.rumble());
+    }
+
+    #[derive(Default)]
+    struct RumbleHAL {
+        rumble: Vec<bool>,
+    }
+
+    impl HAL for RumbleHAL {
+        fn is_joypad_pressed(&self, _: Joypad) -> bool {
+            false
+        }
+
+        fn serial_callback(&mut self, _: u8) -> u8 {
+            0xFF
+        }
+
+        fn set_rumble(&mut self, enabled: bool) {
+            self.rumble.push(enabled);
+        }
+    }
+
+    #[test]
+    fn it_should_forward_rumble_to_the_hal() {
+        let hal = Rc::new(RefCell::new(RumbleHAL::default()));
+        let cartridge = Cartridge::mbc5(rom(2), 0x2000, true);
+        let mut bus = Bus::with_cartridge(cartridge, hal.clone());
+
+        bus.write_cartridge(0x4000, 0x08);
+        bus.write_cartridge(0x4000, 0x0F);
+        bus.write_cartridge(0x4000, 0x00);
+
+        // Only changes are forwarded
+        assert_eq!(vec![true, false], hal.borrow().rumble);
+    }
+}
@@ -0,0 +1,118 @@
+use alloc::vec::{self, Vec};
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub struct RAM {
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+impl RAM {
+    pub fn new(size: usize) -> Self {
+        RAM {
+            data: vec::from_elem(0, size),
+            dirty: false,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    pub fn read(&self, offset: usize) -> u8 {
+        if self.data.is_empty() {
+            return 0xFF;
+        }
+
+        // Offsets past the end of smaller chips wrap around
+        self.data[offset % self.data.len()]
+    }
+
+    pub fn write(&mut self, offset: usize, value: u8) {
+        if self.data.is_empty() {
+            return;
+        }
+
+        let len = self.data.len();
+        let byte = &mut self.data[offset % len];
+
+        if *byte != value {
+            *byte = value;
+            self.dirty = true;
+        }
+    }
+
+    pub fn load(&mut self, data: &[u8]) {
+        let len = self.data.len().min(data.len());
+        self.data[..len].copy_from_slice(&data[..len]);
+
+        self.dirty = false;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let data = state.read_bytes()?;
+
+        if data.len() != self.data.len() {
+            return Err(StateError::InvalidValue("cartridge RAM"));
+        }
+
+        self.restore(data);
+
+        Ok(())
+    }
+
+    /// Replaces the contents from a save state, unlike `load` the RAM is
+    /// marked dirty if this changes it.
+    pub fn restore(&mut self, data: &[u8]) {
+        let len = self.data.len().min(data.len());
+
+        if self.data[..len] != data[..len] {
+            self.data[..len].copy_from_slice(&data[..len]);
+            self.dirty = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_be_dirtied_by_writes_that_change_it() {
+        let mut ram = RAM::new(0x2000);
+        assert!(!ram.dirty());
+
+        ram.write(0x0000, 0x00);
+        assert!(!ram.dirty());
+
+        ram.write(0x0000, 0x12);
+        assert!(ram.dirty());
+
+        ram.clear_dirty();
+        assert!(!ram.dirty());
+    }
+
+    #[test]
+    fn it_should_be_clean_after_loading() {
+        let mut ram = RAM::new(0x2000);
+        ram.write(0x0000, 0x12);
+
+        ram.load(&[0x34, 0x56]);
+
+        assert!(!ram.dirty());
+        assert_eq!(0x34, ram.read(0x0000));
+        assert_eq!(0x56, ram.read(0x2001));
+    }
+}
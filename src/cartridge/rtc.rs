@@ -0,0 +1,415 @@
+use alloc::vec::Vec;
+use bitfield::bitfield;
+use std::convert::TryInto;
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+// The RTC is clocked from a 32.768 kHz crystal, which works out at one
+// second every 1048576 M-cycles at the normal clock speed
+const M_CYCLES_PER_SECOND: u32 = 1_048_576;
+
+// Length of the footer most emulators append to MBC3 saves: the live and latched
+// registers as 32-bit values followed by a 64-bit UNIX timestamp. Some older
+// emulators write a 32-bit timestamp instead.
+const SAVE_FOOTER_LEN: usize = 48;
+const SAVE_FOOTER_LEN_32BIT_TIMESTAMP: usize = 44;
+
+bitfield! {
+    #[derive(Clone, Copy, Default)]
+    pub struct DH(u8);
+    //impl Debug;
+    u8;
+    pub day_carry, set_day_carry: 7;
+    pub halt, _: 6;
+    pub day_high, set_day_high: 0;
+}
+
+impl Into<u8> for DH {
+    fn into(self) -> u8 {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct RTCRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days_low: u8,
+    days_high: DH,
+}
+
+impl RTCRegisters {
+    fn to_array(self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days_low,
+            self.days_high.0,
+        ]
+    }
+
+    fn from_array(values: [u8; 5]) -> Self {
+        RTCRegisters {
+            seconds: values[0] & 0x3F,
+            minutes: values[1] & 0x3F,
+            hours: values[2] & 0x1F,
+            days_low: values[3],
+            days_high: DH(values[4] & 0b1100_0001),
+        }
+    }
+
+    fn days(&self) -> u16 {
+        u16::from_le_bytes([self.days_low, self.days_high.day_high() as u8])
+    }
+
+    fn set_days(&mut self, value: u16) {
+        let [low, high] = value.to_le_bytes();
+
+        self.days_low = low;
+        self.days_high.set_day_high(high & 0x01 != 0);
+    }
+
+    fn increment(&mut self) {
+        // Each counter only carries when it reaches its real limit, values
+        // that have been written out of range count up until they wrap
+        // around the width of the register without carrying
+        if !increment_with_limit(&mut self.seconds, 60, 0x3F) {
+            return;
+        }
+
+        if !increment_with_limit(&mut self.minutes, 60, 0x3F) {
+            return;
+        }
+
+        if !increment_with_limit(&mut self.hours, 24, 0x1F) {
+            return;
+        }
+
+        let days = self.days() + 1;
+
+        if days > 0x1FF {
+            // The carry bit stays set until it is cleared by the game
+            self.days_high.set_day_carry(true);
+        }
+
+        self.set_days(days & 0x1FF);
+    }
+
+    fn advance(&mut self, mut seconds: u64) {
+        // Step through any out of range values one second at a time so that
+        // they wrap the same way they would have while running
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.increment();
+            seconds -= 1;
+        }
+
+        let total = seconds
+            + u64::from(self.seconds)
+            + u64::from(self.minutes) * 60
+            + u64::from(self.hours) * 60 * 60
+            + u64::from(self.days()) * 60 * 60 * 24;
+
+        let days = total / (60 * 60 * 24);
+
+        if days > 0x1FF {
+            self.days_high.set_day_carry(true);
+        }
+
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / (60 * 60) % 24) as u8;
+        self.set_days((days & 0x1FF) as u16);
+    }
+}
+
+fn increment_with_limit(value: &mut u8, limit: u8, mask: u8) -> bool {
+    let next = value.wrapping_add(1);
+
+    if next == limit {
+        *value = 0;
+        true
+    } else {
+        *value = next & mask;
+        false
+    }
+}
+
+pub struct RTC {
+    live: RTCRegisters,
+    latched: RTCRegisters,
+    counter: u32,
+    latch_armed: bool,
+}
+
+impl RTC {
+    pub fn new() -> Self {
+        RTC {
+            live: RTCRegisters::default(),
+            latched: RTCRegisters::default(),
+            counter: 0,
+            latch_armed: false,
+        }
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.latched.seconds,
+            0x09 => self.latched.minutes,
+            0x0A => self.latched.hours,
+            0x0B => self.latched.days_low,
+            0x0C => self.latched.days_high.0 & 0b1100_0001,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => {
+                // Writing the seconds register also resets the sub-second divider
+                self.live.seconds = value & 0x3F;
+                self.counter = 0;
+            }
+            0x09 => self.live.minutes = value & 0x3F,
+            0x0A => self.live.hours = value & 0x1F,
+            0x0B => self.live.days_low = value,
+            0x0C => self.live.days_high = DH(value & 0b1100_0001),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        // Writing 0x00 followed by 0x01 copies the live registers into the latched ones
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.live;
+        }
+
+        self.latch_armed = value == 0x00;
+    }
+
+    /// The registers and the time they were saved at, `now` being the current
+    /// UNIX time in seconds.
+    pub fn save_footer(&self, now: u64) -> Vec<u8> {
+        self.live
+            .to_array()
+            .iter()
+            .chain(self.latched.to_array().iter())
+            .flat_map(|&value| u32::from(value).to_le_bytes().to_vec())
+            .chain(now.to_le_bytes().iter().cloned())
+            .collect()
+    }
+
+    /// Restores the registers, adding on the time between the timestamp in the
+    /// footer and `now`.
+    pub fn load_save_footer(&mut self, data: &[u8], now: u64) {
+        let timestamp = match data.len() {
+            SAVE_FOOTER_LEN => u64::from_le_bytes(data[40..48].try_into().unwrap()),
+            SAVE_FOOTER_LEN_32BIT_TIMESTAMP => {
+                u64::from(u32::from_le_bytes(data[40..44].try_into().unwrap()))
+            }
+            _ => return,
+        };
+
+        let registers: Vec<u8> = data[0..40].chunks(4).map(|chunk| chunk[0]).collect();
+
+        self.live = RTCRegisters::from_array(registers[0..5].try_into().unwrap());
+        self.latched = RTCRegisters::from_array(registers[5..10].try_into().unwrap());
+        self.counter = 0;
+
+        // Catch up with the time that passed while the emulator wasn't running
+        if !self.live.days_high.halt() && now > timestamp {
+            self.live.advance(now - timestamp);
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.live.to_array());
+        state.write_bytes(&self.latched.to_array());
+        state.write_u32(self.counter);
+        state.write_bool(self.latch_armed);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut registers = [0; 5];
+
+        state.read_bytes_into(&mut registers, "RTC registers")?;
+        self.live = RTCRegisters::from_array(registers);
+        state.read_bytes_into(&mut registers, "RTC registers")?;
+        self.latched = RTCRegisters::from_array(registers);
+
+        self.counter = state.read_u32()? % M_CYCLES_PER_SECOND;
+        self.latch_armed = state.read_bool()?;
+
+        Ok(())
+    }
+
+    pub fn tick_m_cycle(&mut self) {
+        if self.live.days_high.halt() {
+            return;
+        }
+
+        self.counter += 1;
+
+        if self.counter == M_CYCLES_PER_SECOND {
+            self.counter = 0;
+            self.live.increment();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick_seconds(rtc: &mut RTC, seconds: u32) {
+        for _ in 0..seconds * M_CYCLES_PER_SECOND {
+            rtc.tick_m_cycle();
+        }
+    }
+
+    #[test]
+    fn it_should_only_update_when_latched() {
+        let mut rtc = RTC::new();
+        tick_seconds(&mut rtc, 2);
+
+        assert_eq!(0, rtc.read(0x08));
+
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+
+        assert_eq!(2, rtc.read(0x08));
+    }
+
+    #[test]
+    fn it_should_not_latch_without_a_preceding_zero() {
+        let mut rtc = RTC::new();
+        tick_seconds(&mut rtc, 1);
+
+        rtc.write_latch(0x01);
+
+        assert_eq!(0, rtc.read(0x08));
+    }
+
+    #[test]
+    fn it_should_carry_between_registers() {
+        let mut rtc = RTC::new();
+        rtc.write(0x08, 59);
+        rtc.write(0x09, 59);
+        rtc.write(0x0A, 23);
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, 0x01);
+
+        tick_seconds(&mut rtc, 1);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+
+        assert_eq!(0, rtc.read(0x08));
+        assert_eq!(0, rtc.read(0x09));
+        assert_eq!(0, rtc.read(0x0A));
+        assert_eq!(0, rtc.read(0x0B));
+        assert_eq!(0x80, rtc.read(0x0C));
+    }
+
+    #[test]
+    fn it_should_wrap_out_of_range_values_without_carrying() {
+        let mut rtc = RTC::new();
+        rtc.write(0x08, 63);
+
+        tick_seconds(&mut rtc, 1);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+
+        assert_eq!(0, rtc.read(0x08));
+        assert_eq!(0, rtc.read(0x09));
+    }
+
+    #[test]
+    fn it_should_round_trip_through_the_save_footer() {
+        let mut rtc = RTC::new();
+        rtc.write(0x08, 12);
+        rtc.write(0x09, 34);
+        rtc.write(0x0A, 5);
+        rtc.write(0x0B, 0x42);
+        rtc.write(0x0C, 0x41);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+
+        let footer = rtc.save_footer(1_000_000);
+        assert_eq!(SAVE_FOOTER_LEN, footer.len());
+
+        let mut loaded = RTC::new();
+        loaded.load_save_footer(&footer, 1_000_000);
+
+        assert_eq!(12, loaded.read(0x08));
+        assert_eq!(34, loaded.read(0x09));
+        assert_eq!(5, loaded.read(0x0A));
+        assert_eq!(0x42, loaded.read(0x0B));
+        assert_eq!(0x41, loaded.read(0x0C));
+    }
+
+    #[test]
+    fn it_should_catch_up_with_the_time_since_saving() {
+        let mut rtc = RTC::new();
+        rtc.write(0x08, 50);
+        rtc.write(0x09, 59);
+
+        let footer = rtc.save_footer(1_000_000);
+
+        let mut loaded = RTC::new();
+        loaded.load_save_footer(&footer, 1_000_000 + 25 * 60 * 60 + 15);
+        loaded.write_latch(0x00);
+        loaded.write_latch(0x01);
+
+        assert_eq!(5, loaded.read(0x08));
+        assert_eq!(0, loaded.read(0x09));
+        assert_eq!(2, loaded.read(0x0A));
+        assert_eq!(1, loaded.read(0x0B));
+    }
+
+    #[test]
+    fn it_should_not_catch_up_while_halted() {
+        let mut rtc = RTC::new();
+        rtc.write(0x08, 50);
+        rtc.write(0x0C, 0x40);
+
+        let footer = rtc.save_footer(1_000_000);
+
+        let mut loaded = RTC::new();
+        loaded.load_save_footer(&footer, 1_000_000 + 60 * 60);
+        loaded.write_latch(0x00);
+        loaded.write_latch(0x01);
+
+        assert_eq!(50, loaded.read(0x08));
+        assert_eq!(0, loaded.read(0x0A));
+    }
+
+    #[test]
+    fn it_should_advance_by_whole_days() {
+        let mut registers = RTCRegisters {
+            hours: 23,
+            days_low: 0xFF,
+            days_high: DH(0x01),
+            ..RTCRegisters::default()
+        };
+
+        registers.advance(60 * 60);
+
+        assert_eq!(0, registers.hours);
+        assert_eq!(0, registers.days());
+        assert!(registers.days_high.day_carry());
+    }
+
+    #[test]
+    fn it_should_not_count_while_halted() {
+        let mut rtc = RTC::new();
+        rtc.write(0x0C, 0x40);
+
+        tick_seconds(&mut rtc, 1);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+
+        assert_eq!(0, rtc.read(0x08));
+        assert_eq!(0x40, rtc.read(0x0C));
+    }
+}
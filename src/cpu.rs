@@ -8,7 +8,9 @@ mod registers;
 use alu::AluOp;
 use instructions::{AddArg, Condition, IncDecArg, Instruction, LoadArgs, Register};
 use io::{In16, In8, Out16, Out8};
-use registers::Registers;
+pub use registers::Registers;
+
+use crate::state::{StateError, StateReader, StateWriter};
 
 pub use flags::Flags;
 pub use interrupt::Interrupt;
@@ -31,9 +33,13 @@ pub struct CPU<B: Bus> {
 
 impl<B: Bus> CPU<B> {
     pub fn new(bus: B) -> Self {
+        CPU::with_registers(bus, Registers::default())
+    }
+
+    pub fn with_registers(bus: B, registers: Registers) -> Self {
         CPU {
             bus,
-            registers: Registers::default(),
+            registers,
             halt: false,
             ime: false,
         }
@@ -51,10 +57,40 @@ impl<B: Bus> CPU<B> {
         &self.bus
     }
 
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
     pub fn registers(&self) -> &Registers {
         &self.registers
     }
 
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.registers = registers;
+    }
+
+    pub fn set_halt(&mut self, value: bool) {
+        self.halt = value;
+    }
+
+    pub fn set_ime(&mut self, value: bool) {
+        self.ime = value;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        self.registers.save_state(state);
+        state.write_bool(self.halt);
+        state.write_bool(self.ime);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.registers.load_state(state)?;
+        self.halt = state.read_bool()?;
+        self.ime = state.read_bool()?;
+
+        Ok(())
+    }
+
     fn fetch_next(&mut self) -> u8 {
         let next = self.bus.read_m_cycle(self.registers.pc());
         self.registers.set_pc(self.registers.pc().wrapping_add(1));
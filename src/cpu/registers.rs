@@ -1,4 +1,6 @@
 use super::flags::Flags;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::Model;
 
 #[derive(Debug)]
 pub struct Registers {
@@ -32,6 +34,76 @@ impl Default for Registers {
 }
 
 impl Registers {
+    /// The state of the registers at power on, before the boot ROM has run.
+    pub fn power_on() -> Self {
+        Registers {
+            a: 0x00,
+            b: 0x00,
+            c: 0x00,
+            d: 0x00,
+            e: 0x00,
+            f: Flags::empty(),
+            h: 0x00,
+            l: 0x00,
+            pc: 0x0000,
+            sp: 0x0000,
+        }
+    }
+
+    /// The state the boot ROM of each model leaves the registers in. The DMG and
+    /// MGB boot ROMs leave the half carry and carry flags set unless the header
+    /// checksum is 0x00.
+    pub fn post_boot(model: Model, header_checksum: u8) -> Self {
+        let checksum_flags = if header_checksum != 0 {
+            Flags::HalfCarry | Flags::Carry
+        } else {
+            Flags::empty()
+        };
+
+        let (a, f, b, c, d, e, h, l) = match model {
+            Model::DMG0 => (0x01, Flags::empty(), 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03),
+            Model::DMG => (
+                0x01,
+                Flags::Zero | checksum_flags,
+                0x00,
+                0x13,
+                0x00,
+                0xD8,
+                0x01,
+                0x4D,
+            ),
+            Model::MGB => (
+                0xFF,
+                Flags::Zero | checksum_flags,
+                0x00,
+                0x13,
+                0x00,
+                0xD8,
+                0x01,
+                0x4D,
+            ),
+            Model::SGB => (0x01, Flags::empty(), 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            Model::SGB2 => (0xFF, Flags::empty(), 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            // The colour models run DMG games in compatibility mode, the AGB
+            // boot ROM increments B which also clears the zero flag
+            Model::CGB => (0x11, Flags::Zero, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C),
+            Model::AGB => (0x11, Flags::empty(), 0x01, 0x00, 0x00, 0x08, 0x00, 0x7C),
+        };
+
+        Registers {
+            a,
+            b,
+            c,
+            d,
+            e,
+            f,
+            h,
+            l,
+            pc: 0x0100,
+            sp: 0xFFFE,
+        }
+    }
+
     pub fn a(&self) -> u8 {
         self.a
     }
@@ -155,12 +227,52 @@ impl Registers {
     pub fn set_sp(&mut self, value: u16) {
         self.sp = value;
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.af());
+        state.write_u16(self.bc());
+        state.write_u16(self.de());
+        state.write_u16(self.hl());
+        state.write_u16(self.pc);
+        state.write_u16(self.sp);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.set_af(state.read_u16()?);
+        self.set_bc(state.read_u16()?);
+        self.set_de(state.read_u16()?);
+        self.set_hl(state.read_u16()?);
+        self.pc = state.read_u16()?;
+        self.sp = state.read_u16()?;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn it_should_match_the_default_dmg_state() {
+        let registers = Registers::post_boot(Model::DMG, 0x42);
+        let default = Registers::default();
+
+        assert_eq!(default.af(), registers.af());
+        assert_eq!(default.bc(), registers.bc());
+        assert_eq!(default.de(), registers.de());
+        assert_eq!(default.hl(), registers.hl());
+        assert_eq!(default.sp(), registers.sp());
+        assert_eq!(default.pc(), registers.pc());
+    }
+
+    #[test]
+    fn it_should_clear_carry_flags_for_a_zero_header_checksum() {
+        let registers = Registers::post_boot(Model::DMG, 0x00);
+
+        assert_eq!(Flags::Zero, registers.f());
+    }
+
     #[test]
     fn test_af() {
         let mut registers = Registers::default();
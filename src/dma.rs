@@ -0,0 +1,147 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// OAM DMA copies 160 bytes into OAM, one byte every M-cycle. While it runs
+/// it has the bus to itself, so the CPU can only use HRAM and the IO
+/// registers.
+pub struct DMA {
+    source: u8,
+    // The source and the next byte to copy while a transfer is running
+    transfer_source: u8,
+    offset: Option<u8>,
+    // A transfer starts on the M-cycle after the write, any transfer that is
+    // already running carries on until then
+    starting: bool,
+    bus_value: u8,
+}
+
+impl DMA {
+    pub fn new() -> Self {
+        DMA {
+            source: 0xFF,
+            transfer_source: 0xFF,
+            offset: None,
+            starting: false,
+            bus_value: 0xFF,
+        }
+    }
+
+    pub fn source(&self) -> u8 {
+        self.source
+    }
+
+    /// Sets the register without starting a transfer.
+    pub fn set_source(&mut self, value: u8) {
+        self.source = value;
+    }
+
+    pub fn start(&mut self, value: u8) {
+        self.source = value;
+        self.starting = true;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.offset.is_some()
+    }
+
+    /// The last byte copied, which is what the CPU sees when it reads from
+    /// anywhere the transfer has the bus.
+    pub fn bus_value(&self) -> u8 {
+        self.bus_value
+    }
+
+    pub fn set_bus_value(&mut self, value: u8) {
+        self.bus_value = value;
+    }
+
+    /// Advances the transfer by an M-cycle, returning the address to copy from
+    /// and the offset in OAM to copy to if a byte is copied.
+    pub fn tick_m_cycle(&mut self) -> Option<(u16, usize)> {
+        let copy = self.offset.map(|offset| {
+            // Sources above WRAM read from WRAM instead
+            let source = match self.transfer_source {
+                0xE0..=0xFF => self.transfer_source - 0x20,
+                source => source,
+            };
+
+            (u16::from_be_bytes([source, offset]), usize::from(offset))
+        });
+
+        self.offset = match self.offset {
+            Some(offset) if offset < 159 => Some(offset + 1),
+            _ => None,
+        };
+
+        if self.starting {
+            self.starting = false;
+            self.transfer_source = self.source;
+            self.offset = Some(0);
+        }
+
+        copy
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.source);
+        state.write_u8(self.transfer_source);
+        state.write_bool(self.offset.is_some());
+        state.write_u8(self.offset.unwrap_or(0));
+        state.write_bool(self.starting);
+        state.write_u8(self.bus_value);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.source = state.read_u8()?;
+        self.transfer_source = state.read_u8()?;
+
+        let active = state.read_bool()?;
+        let offset = state.read_u8()?;
+
+        self.offset = match offset {
+            _ if !active => None,
+            0..=159 => Some(offset),
+            _ => return Err(StateError::InvalidValue("DMA offset")),
+        };
+
+        self.starting = state.read_bool()?;
+        self.bus_value = state.read_u8()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_copy_a_byte_every_m_cycle_after_starting() {
+        let mut dma = DMA::new();
+        dma.start(0xC1);
+
+        assert_eq!(None, dma.tick_m_cycle());
+        assert!(dma.is_active());
+
+        for offset in 0..160 {
+            assert_eq!(Some((0xC100 + offset as u16, offset)), dma.tick_m_cycle());
+        }
+
+        assert!(!dma.is_active());
+        assert_eq!(None, dma.tick_m_cycle());
+    }
+
+    #[test]
+    fn it_should_restart_from_the_beginning() {
+        let mut dma = DMA::new();
+        dma.start(0xC0);
+
+        for _ in 0..11 {
+            dma.tick_m_cycle();
+        }
+
+        // The first transfer copies one more byte before the new one starts
+        dma.start(0xFE);
+
+        assert_eq!(Some((0xC00A, 10)), dma.tick_m_cycle());
+        assert_eq!(Some((0xDE00, 0)), dma.tick_m_cycle());
+    }
+}
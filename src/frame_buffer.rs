@@ -0,0 +1,91 @@
+use alloc::vec::{self, Vec};
+
+use super::hal::Color;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// The shades drawn by the PPU, one per pixel from left to right and top to bottom.
+#[derive(Clone)]
+pub struct FrameBuffer {
+    pixels: Vec<Color>,
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        FrameBuffer::new()
+    }
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        FrameBuffer {
+            pixels: vec::from_elem(Color::White, SCREEN_WIDTH * SCREEN_HEIGHT),
+        }
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * SCREEN_WIDTH + x] = color;
+    }
+
+    pub fn clear(&mut self) {
+        for pixel in &mut self.pixels {
+            *pixel = Color::White;
+        }
+    }
+
+    /// The shade of each pixel from 0 (white) to 3 (black).
+    pub fn shades(&self) -> Vec<u8> {
+        self.pixels.iter().map(|&color| color as u8).collect()
+    }
+
+    /// Four bytes per pixel in R, G, B, A order.
+    pub fn to_rgba8888(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| color.into_rgba().to_vec())
+            .collect()
+    }
+
+    pub fn to_rgb565(&self) -> Vec<u16> {
+        self.pixels
+            .iter()
+            .map(|color| color.into_rgb565())
+            .collect()
+    }
+
+    pub fn to_xrgb8888(&self) -> Vec<u32> {
+        self.pixels
+            .iter()
+            .map(|color| color.into_xrgb8888())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_convert_pixel_formats() {
+        let mut frame_buffer = FrameBuffer::new();
+        frame_buffer.set_pixel(1, 0, Color::Black);
+
+        assert_eq!(Color::Black, frame_buffer.pixel(1, 0));
+        assert_eq!(vec![0, 3, 0], frame_buffer.shades()[..3].to_vec());
+        assert_eq!(
+            [155, 188, 15, 255, 15, 56, 15, 255],
+            frame_buffer.to_rgba8888()[..8]
+        );
+        assert_eq!(0x00_0F_38_0F, frame_buffer.to_xrgb8888()[1]);
+        assert_eq!(0x09C1, frame_buffer.to_rgb565()[1]);
+    }
+}
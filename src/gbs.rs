@@ -0,0 +1,291 @@
+use alloc::vec::Vec;
+use std::fmt;
+
+use crate::ROM;
+
+const MAGIC: &[u8; 3] = b"GBS";
+const HEADER_LEN: usize = 0x70;
+// The most ROM MBC5 can bank
+const MAX_ROM_SIZE: usize = 0x80_0000;
+
+// Where the player's code goes in the cartridge built around the music data,
+// out of the way of the data which is loaded at 0x0400 or above
+pub const DRIVER_ADDRESS: u16 = 0x0150;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum GbsError {
+    Truncated { len: usize },
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    NoSongs,
+    InvalidLoadAddress(u16),
+    TooLarge { len: usize },
+}
+
+impl fmt::Display for GbsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            GbsError::Truncated { len } => write!(
+                f,
+                "file is {} bytes, too short to contain a GBS header",
+                len
+            ),
+            GbsError::InvalidMagic => write!(f, "not a GBS file"),
+            GbsError::UnsupportedVersion(version) => {
+                write!(f, "unsupported GBS version {}", version)
+            }
+            GbsError::NoSongs => write!(f, "GBS file has no songs"),
+            GbsError::InvalidLoadAddress(addr) => {
+                write!(f, "invalid load address {:#06X}", addr)
+            }
+            GbsError::TooLarge { len } => write!(
+                f,
+                "{} bytes of data at the load address don't fit in a cartridge",
+                len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GbsError {}
+
+/// A GBS file, the sound engine and music of a game ripped out along with
+/// the addresses to call to start and play each song.
+pub struct GBS {
+    songs: u8,
+    first_song: u8,
+    load_address: u16,
+    init_address: u16,
+    play_address: u16,
+    stack_pointer: u16,
+    timer_modulo: u8,
+    timer_control: u8,
+    title: String,
+    author: String,
+    copyright: String,
+    data: Vec<u8>,
+}
+
+fn header_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as char)
+        .collect()
+}
+
+impl GBS {
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, GbsError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(GbsError::Truncated { len: bytes.len() });
+        }
+
+        if &bytes[..MAGIC.len()] != MAGIC {
+            return Err(GbsError::InvalidMagic);
+        }
+
+        if bytes[0x03] != 1 {
+            return Err(GbsError::UnsupportedVersion(bytes[0x03]));
+        }
+
+        if bytes[0x04] == 0 {
+            return Err(GbsError::NoSongs);
+        }
+
+        let word = |addr: usize| u16::from_le_bytes([bytes[addr], bytes[addr + 1]]);
+        let load_address = word(0x06);
+
+        // The data has to leave room for the player's code and fit in ROM
+        if !(0x0400..0x8000).contains(&load_address) {
+            return Err(GbsError::InvalidLoadAddress(load_address));
+        }
+
+        let len = bytes.len() - HEADER_LEN;
+
+        if usize::from(load_address) + len > MAX_ROM_SIZE {
+            return Err(GbsError::TooLarge { len });
+        }
+
+        Ok(GBS {
+            songs: bytes[0x04],
+            first_song: bytes[0x05],
+            load_address,
+            init_address: word(0x08),
+            play_address: word(0x0A),
+            stack_pointer: word(0x0C),
+            timer_modulo: bytes[0x0E],
+            timer_control: bytes[0x0F],
+            title: header_string(&bytes[0x10..0x30]),
+            author: header_string(&bytes[0x30..0x50]),
+            copyright: header_string(&bytes[0x50..0x70]),
+            data: bytes[HEADER_LEN..].to_vec(),
+        })
+    }
+
+    pub fn songs(&self) -> u8 {
+        self.songs
+    }
+
+    /// The song to start with, counting from 0.
+    pub fn first_song(&self) -> u8 {
+        self.first_song.saturating_sub(1).min(self.songs - 1)
+    }
+
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    pub fn init_address(&self) -> u16 {
+        self.init_address
+    }
+
+    pub fn play_address(&self) -> u16 {
+        self.play_address
+    }
+
+    pub fn stack_pointer(&self) -> u16 {
+        self.stack_pointer
+    }
+
+    pub fn timer_modulo(&self) -> u8 {
+        self.timer_modulo
+    }
+
+    pub fn timer_control(&self) -> u8 {
+        self.timer_control
+    }
+
+    /// The play routine is called by the timer interrupt rather than VBlank
+    /// when the timer is enabled in TAC.
+    pub fn uses_timer(&self) -> bool {
+        self.timer_control & 0x04 != 0
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn copyright(&self) -> &str {
+        &self.copyright
+    }
+
+    /// Builds an MBC5 cartridge with the data at the load address and a small
+    /// driver that calls the init routine and then waits for interrupts, which
+    /// call the play routine.
+    pub fn to_rom(&self) -> ROM {
+        let load_address = usize::from(self.load_address);
+        let len = (load_address + self.data.len())
+            .next_power_of_two()
+            .max(0x8000);
+
+        let mut rom = vec![0; len];
+        rom[load_address..load_address + self.data.len()].copy_from_slice(&self.data);
+
+        // The RST vectors jump to the same offsets from the load address
+        for vector in (0x00..0x40).step_by(8) {
+            let [low, high] = (self.load_address + vector as u16).to_le_bytes();
+            rom[vector..vector + 3].copy_from_slice(&[0xC3, low, high]);
+        }
+
+        // CALL play; RETI on the VBlank and timer interrupts
+        let [low, high] = self.play_address.to_le_bytes();
+        rom[0x40..0x44].copy_from_slice(&[0xCD, low, high, 0xD9]);
+        rom[0x50..0x54].copy_from_slice(&[0xCD, low, high, 0xD9]);
+
+        // NOP; JP DRIVER_ADDRESS
+        let [low, high] = DRIVER_ADDRESS.to_le_bytes();
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, low, high]);
+
+        // CALL init; idle: EI; HALT; JR idle
+        let driver = usize::from(DRIVER_ADDRESS);
+        let [low, high] = self.init_address.to_le_bytes();
+        rom[driver..driver + 7].copy_from_slice(&[0xCD, low, high, 0xFB, 0x76, 0x18, 0xFC]);
+
+        for (byte, c) in rom[0x134..0x143].iter_mut().zip(self.title.bytes()) {
+            *byte = c;
+        }
+
+        rom[0x147] = 0x1A; // MBC5+RAM
+        rom[0x148] = (len / 0x8000).trailing_zeros() as u8;
+        rom[0x149] = 0x02; // 8 KiB
+        rom[0x14D] = rom[0x134..=0x14C].iter().fold(0u8, |checksum, byte| {
+            checksum.wrapping_sub(*byte).wrapping_sub(1)
+        });
+
+        ROM::from(rom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::gbs_header;
+
+    #[test]
+    fn it_should_parse_the_header() {
+        let mut bytes = gbs_header(3, 0x0400);
+        bytes.extend_from_slice(&[0xC9; 0x30]);
+
+        let gbs = GBS::parse(bytes).unwrap();
+
+        assert_eq!(3, gbs.songs());
+        assert_eq!(1, gbs.first_song());
+        assert_eq!(0x0400, gbs.load_address());
+        assert_eq!(0x0410, gbs.init_address());
+        assert_eq!(0x0420, gbs.play_address());
+        assert_eq!(0xDFFF, gbs.stack_pointer());
+        assert_eq!(0xC0, gbs.timer_modulo());
+        assert!(gbs.uses_timer());
+        assert_eq!("TITLE", gbs.title());
+        assert_eq!("AUTHOR", gbs.author());
+        assert_eq!("", gbs.copyright());
+    }
+
+    #[test]
+    fn it_should_reject_invalid_files() {
+        assert_eq!(
+            Err(GbsError::Truncated { len: 3 }),
+            GBS::parse(b"GBS".to_vec()).map(|_| ())
+        );
+
+        let mut bytes = gbs_header(1, 0x0400);
+        bytes[0] = b'X';
+        assert_eq!(Err(GbsError::InvalidMagic), GBS::parse(bytes).map(|_| ()));
+
+        assert_eq!(
+            Err(GbsError::NoSongs),
+            GBS::parse(gbs_header(0, 0x0400)).map(|_| ())
+        );
+
+        assert_eq!(
+            Err(GbsError::InvalidLoadAddress(0x0100)),
+            GBS::parse(gbs_header(1, 0x0100)).map(|_| ())
+        );
+
+        let mut bytes = gbs_header(1, 0x0400);
+        bytes.resize(HEADER_LEN + MAX_ROM_SIZE - 0x03FF, 0);
+        assert_eq!(
+            Err(GbsError::TooLarge {
+                len: MAX_ROM_SIZE - 0x03FF
+            }),
+            GBS::parse(bytes).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn it_should_build_a_cartridge_around_the_data() {
+        let mut bytes = gbs_header(1, 0x3FF0);
+        bytes.extend_from_slice(&[0xAA; 0x20]);
+
+        let rom = GBS::parse(bytes).unwrap().to_rom();
+
+        assert_eq!(Ok(()), rom.validate());
+        assert_eq!(0x8000, rom.rom_size().unwrap());
+        assert_eq!(rom.compute_header_checksum(), rom.header_checksum());
+        assert_eq!("TITLE", rom.title());
+    }
+}
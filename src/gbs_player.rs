@@ -0,0 +1,225 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::cpu::Registers;
+use crate::gbs::{DRIVER_ADDRESS, GBS};
+use crate::{AudioBuffer, Gameboy, HAL};
+
+/// Plays the songs in a GBS file on a Gameboy running a cartridge built
+/// around the music data.
+pub struct GbsPlayer {
+    gbs: GBS,
+    gameboy: Gameboy,
+    track: u8,
+    // Samples made past the end of the last render
+    pending: Vec<f32>,
+}
+
+impl GbsPlayer {
+    /// Starts playing the first song.
+    pub fn new(gbs: GBS, hal: Rc<RefCell<dyn HAL>>) -> Self {
+        let gameboy = Gameboy::new(gbs.to_rom(), hal).expect("GBS cartridge is always valid");
+        let track = gbs.first_song();
+
+        let mut player = GbsPlayer {
+            gbs,
+            gameboy,
+            track,
+            pending: Vec::new(),
+        };
+
+        player.select_track(track);
+        player
+    }
+
+    pub fn gbs(&self) -> &GBS {
+        &self.gbs
+    }
+
+    /// The Gameboy playing the music, for the audio settings and capture.
+    pub fn gameboy(&self) -> &Gameboy {
+        &self.gameboy
+    }
+
+    pub fn gameboy_mut(&mut self) -> &mut Gameboy {
+        &mut self.gameboy
+    }
+
+    /// The song being played, counting from 0.
+    pub fn track(&self) -> u8 {
+        self.track
+    }
+
+    /// Clears the RAM, the sound and the timer and calls the init routine for
+    /// a song, counting from 0. Returns false if there's no such song.
+    pub fn select_track(&mut self, track: u8) -> bool {
+        if track >= self.gbs.songs() {
+            return false;
+        }
+
+        self.track = track;
+        self.pending.clear();
+
+        let cpu = self.gameboy.cpu_mut();
+        let bus = cpu.bus_mut();
+
+        for byte in bus.wram_mut() {
+            *byte = 0;
+        }
+
+        for byte in bus.hram_mut() {
+            *byte = 0;
+        }
+
+        bus.cartridge_mut().load_save_ram(&[0; 8192], 0);
+        bus.write_cartridge(0x0000, 0x0A);
+        bus.write_cartridge(0x2000, 0x01);
+
+        // The play routine is called by whichever interrupt is enabled
+        let inte = if self.gbs.uses_timer() { 0x04 } else { 0x01 };
+
+        for &(addr, value) in &[
+            (0xFF26, 0x00),
+            (0xFF26, 0x80),
+            (0xFF24, 0x77),
+            (0xFF25, 0xFF),
+            (0xFF0F, 0x00),
+            (0xFFFF, inte),
+        ] {
+            // Without ticking, so nothing runs before the driver
+            bus.write(addr, value);
+        }
+
+        bus.timer_mut()
+            .restore_registers(0, 0, self.gbs.timer_modulo(), self.gbs.timer_control());
+
+        // The driver calls init with the song in A, then waits for interrupts
+        let mut registers = Registers::default();
+        registers.set_a(track);
+        registers.set_sp(self.gbs.stack_pointer());
+        registers.set_pc(DRIVER_ADDRESS);
+
+        cpu.set_registers(registers);
+        cpu.set_halt(false);
+        cpu.set_ime(false);
+
+        true
+    }
+
+    pub fn step_frame(&mut self) {
+        self.gameboy.step_frame();
+    }
+
+    /// Plays for the given number of seconds, returning the samples made.
+    pub fn render(&mut self, seconds: f64) -> AudioBuffer {
+        let sample_rate = self.gameboy.audio_buffer().sample_rate();
+        let len = (seconds * f64::from(sample_rate)).round() as usize * 2;
+
+        let mut samples = core::mem::take(&mut self.pending);
+
+        while samples.len() < len {
+            self.gameboy.step_frame();
+            samples.extend_from_slice(self.gameboy.audio_buffer().samples());
+        }
+
+        self.pending = samples.split_off(len);
+
+        let mut audio_buffer = AudioBuffer::new(sample_rate);
+
+        for pair in samples.chunks(2) {
+            audio_buffer.push(pair[0], pair[1]);
+        }
+
+        audio_buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{gbs_header, NullHAL};
+
+    // init: LD (0xC001),A, then a 512 Hz tone on the first pulse channel
+    const INIT: [u8; 32] = [
+        0xEA, 0x01, 0xC0, 0x3E, 0x80, 0xE0, 0x26, 0x3E, 0x77, 0xE0, 0x24, 0x3E, 0xFF, 0xE0, 0x25,
+        0x3E, 0xF0, 0xE0, 0x12, 0x3E, 0x80, 0xE0, 0x11, 0x3E, 0x00, 0xE0, 0x13, 0x3E, 0x87, 0xE0,
+        0x14, 0xC9,
+    ];
+
+    // play: LD A,(0xC000); INC A; LD (0xC000),A; RET
+    const PLAY: [u8; 8] = [0xFA, 0x00, 0xC0, 0x3C, 0xEA, 0x00, 0xC0, 0xC9];
+
+    fn player(timer_control: u8) -> GbsPlayer {
+        // Init at the start of the data with play straight after it
+        let mut bytes = gbs_header(3, 0x0400);
+        bytes[0x08..0x0A].copy_from_slice(&0x0400u16.to_le_bytes());
+        bytes[0x0E..0x10].copy_from_slice(&[0x00, timer_control]);
+        bytes.extend_from_slice(&INIT);
+        bytes.extend_from_slice(&PLAY);
+
+        let hal = Rc::new(RefCell::new(NullHAL));
+        GbsPlayer::new(GBS::parse(bytes).unwrap(), hal)
+    }
+
+    #[test]
+    fn it_should_call_play_on_vblank() {
+        let mut player = player(0x00);
+
+        for _ in 0..10 {
+            player.step_frame();
+        }
+
+        let wram = player.gameboy().cpu().bus().wram();
+
+        assert_eq!(1, player.track());
+        assert_eq!(1, wram[1]);
+        assert!((9..=11).contains(&wram[0]));
+    }
+
+    #[test]
+    fn it_should_call_play_on_the_timer() {
+        // 262,144 Hz overflowing every 256 increments is every 4,096 cycles
+        let mut player = player(0x05);
+
+        for _ in 0..4 {
+            player.step_frame();
+        }
+
+        assert!((67..=70).contains(&player.gameboy().cpu().bus().wram()[0]));
+    }
+
+    #[test]
+    fn it_should_select_tracks() {
+        let mut player = player(0x00);
+
+        for _ in 0..10 {
+            player.step_frame();
+        }
+
+        assert!(player.select_track(2));
+        player.step_frame();
+
+        let wram = player.gameboy().cpu().bus().wram();
+
+        assert_eq!(2, wram[1]);
+        assert!(wram[0] <= 1);
+
+        assert!(!player.select_track(3));
+        assert_eq!(2, player.track());
+    }
+
+    #[test]
+    fn it_should_render_seconds_of_audio() {
+        let mut player = player(0x00);
+        let audio_buffer = player.render(0.25);
+
+        assert_eq!(12000, audio_buffer.len());
+        assert!(audio_buffer
+            .samples()
+            .iter()
+            .any(|sample| sample.abs() > 0.1));
+
+        assert_eq!(12000, player.render(0.25).len());
+    }
+}
@@ -1,6 +1,9 @@
 use std::convert::TryInto;
 
-#[derive(Copy, Clone)]
+use super::audio_buffer::AudioBuffer;
+use super::frame_buffer::FrameBuffer;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Color {
     White = 0,
     LightGrey = 1,
@@ -18,6 +21,18 @@ impl Color {
         }
     }
 
+    pub fn into_rgb565(self) -> u16 {
+        let [r, g, b, _] = self.into_rgba();
+
+        (u16::from(r) >> 3) << 11 | (u16::from(g) >> 2) << 5 | u16::from(b) >> 3
+    }
+
+    pub fn into_xrgb8888(self) -> u32 {
+        let [r, g, b, _] = self.into_rgba();
+
+        u32::from_be_bytes([0, r, g, b])
+    }
+
     pub fn into_rgba_f32(self) -> [f32; 4] {
         self.into_rgba()
             .iter()
@@ -43,6 +58,17 @@ pub enum Joypad {
 
 pub trait HAL {
     fn is_joypad_pressed(&self, button: Joypad) -> bool;
-    fn put_pixel(&mut self, line: usize, x: usize, color: Color);
     fn serial_callback(&mut self, value: u8) -> u8;
+
+    /// Called once per frame when the PPU has finished drawing the visible lines.
+    fn frame_ready(&mut self, _frame_buffer: &FrameBuffer) {}
+    /// Called once per frame with the samples the APU made during it.
+    fn audio_ready(&mut self, _audio_buffer: &AudioBuffer) {}
+    fn set_rumble(&mut self, _enabled: bool) {}
+
+    /// The current UNIX time in seconds, for the RTC to catch up with the time
+    /// between saving and loading the cartridge RAM.
+    fn unix_time(&self) -> u64 {
+        0
+    }
 }
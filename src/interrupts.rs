@@ -1,3 +1,4 @@
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::Interrupt;
 use bitfield::bitfield;
 
@@ -20,8 +21,9 @@ impl Into<u8> for INTF {
 }
 
 impl INTF {
-    fn get_highest_priority(&self) -> Option<Interrupt> {
-        let n = self.0.trailing_zeros() as usize;
+    /// The highest priority interrupt that is both requested and enabled.
+    fn get_highest_priority(&self, inte: &INTE) -> Option<Interrupt> {
+        let n = (self.0 & inte.0).trailing_zeros() as usize;
 
         match n {
             0 => Some(Interrupt::VBlank),
@@ -65,6 +67,15 @@ impl Interrupts {
         }
     }
 
+    /// The boot ROM leaves the VBlank interrupt requested, and the unused upper
+    /// bits of IF always read as set.
+    pub fn post_boot() -> Self {
+        Interrupts {
+            intf: INTF(0xE1),
+            inte: INTE(0x00),
+        }
+    }
+
     pub fn intf(&self) -> INTF {
         self.intf.clone()
     }
@@ -81,12 +92,24 @@ impl Interrupts {
         self.inte = INTE(value);
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.intf.0);
+        state.write_u8(self.inte.0);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.intf = INTF(state.read_u8()?);
+        self.inte = INTE(state.read_u8()?);
+
+        Ok(())
+    }
+
     pub fn should_handle_interrupt(&self) -> bool {
         (self.inte.0 & self.intf.0 & 0b00011111) > 0
     }
 
     pub fn pop_interrupt(&mut self) -> Option<Interrupt> {
-        let interrupt = self.intf.get_highest_priority();
+        let interrupt = self.intf.get_highest_priority(&self.inte);
 
         match interrupt {
             Some(Interrupt::VBlank) => self.intf.set_vblank(false),
@@ -123,4 +146,15 @@ mod tests {
         assert_eq!(0x0058, Interrupt::Serial.to_vector());
         assert_eq!(0x0060, Interrupt::Joypad.to_vector());
     }
+
+    #[test]
+    fn it_should_skip_requested_interrupts_that_are_not_enabled() {
+        let mut interrupts = Interrupts::new();
+        interrupts.set_inte(0x04);
+        interrupts.trigger_interrupt(Interrupt::VBlank);
+        interrupts.trigger_interrupt(Interrupt::Timer);
+
+        assert!(matches!(interrupts.pop_interrupt(), Some(Interrupt::Timer)));
+        assert!(interrupts.intf().vblank());
+    }
 }
@@ -4,6 +4,7 @@ use core::cell::RefCell;
 
 use super::hal::Joypad as Button;
 use super::hal::HAL;
+use crate::state::{StateError, StateReader, StateWriter};
 
 #[derive(Eq, PartialEq)]
 pub enum SelectState {
@@ -106,9 +107,22 @@ impl Into<u8> for JOYP {
     }
 }
 
+const BUTTONS: [Button; 8] = [
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+    Button::A,
+    Button::B,
+    Button::Start,
+    Button::Select,
+];
+
 #[derive(Clone)]
 pub struct Joypad {
     joyp: JOYP,
+    // Input played back in place of the HAL's, one bit per button
+    replayed_input: Option<u8>,
 
     hal: Rc<RefCell<dyn HAL>>,
 }
@@ -117,6 +131,7 @@ impl Joypad {
     pub fn new(hal: Rc<RefCell<dyn HAL>>) -> Self {
         Joypad {
             joyp: JOYP(0x0F),
+            replayed_input: None,
 
             hal,
         }
@@ -124,10 +139,8 @@ impl Joypad {
 
     pub fn joyp(&self) -> JOYP {
         //self.joyp.clone()
-        let hal = self.hal.borrow();
-
         let get_button = |button| {
-            if hal.is_joypad_pressed(button) {
+            if self.is_pressed(button) {
                 ButtonState::Pressed
             } else {
                 ButtonState::Released
@@ -161,6 +174,28 @@ impl Joypad {
         joyp
     }
 
+    /// The buttons held down, one bit per button in the order they're
+    /// declared in `hal::Joypad`.
+    pub fn input(&self) -> u8 {
+        BUTTONS
+            .iter()
+            .filter(|&&button| self.is_pressed(button))
+            .fold(0, |input, &button| input | 1 << button as u8)
+    }
+
+    /// Plays back input from `input` in place of the HAL's until it's set
+    /// back to `None`.
+    pub fn set_replayed_input(&mut self, input: Option<u8>) {
+        self.replayed_input = input;
+    }
+
+    fn is_pressed(&self, button: Button) -> bool {
+        match self.replayed_input {
+            Some(input) => input.bit(button as usize),
+            None => self.hal.borrow().is_joypad_pressed(button),
+        }
+    }
+
     pub fn set_joyp(&mut self, value: u8) {
         self.joyp.set_select_buttons(if !value.bit(5) {
             SelectState::Selected
@@ -175,6 +210,16 @@ impl Joypad {
         })
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.joyp.0);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.joyp = JOYP(state.read_u8()?);
+
+        Ok(())
+    }
+
     fn should_interrupt(&mut self, previous: &Joypad) -> bool {
         (0..4)
             .into_iter()
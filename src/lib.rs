@@ -1,57 +1,657 @@
 //#![no_std]
 extern crate alloc;
 
+mod apu;
+mod audio_buffer;
+mod audio_capture;
+mod bess;
 mod bus;
 mod cartridge;
 mod cpu;
+mod dma;
+mod frame_buffer;
+mod gbs;
+mod gbs_player;
 mod hal;
 mod interrupts;
 mod joypad;
+mod model;
 mod ppu;
+mod rewind;
 mod rom;
 mod serial;
+mod state;
+#[cfg(test)]
+mod test_utils;
 mod timer;
 // mod ffi;
 // mod rom;
 
+pub use apu::Channel;
+pub use audio_buffer::AudioBuffer;
+pub use audio_capture::AudioCapture;
 pub use cpu::Flags;
+pub use frame_buffer::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use gbs::{GbsError, GBS};
+pub use gbs_player::GbsPlayer;
 pub use hal::{Color, Joypad, HAL};
-pub use rom::ROM;
+pub use model::Model;
+pub use rewind::Rewind;
+pub use rom::{CGBMode, CartridgeFeatures, Destination, HeaderReport, RomError, RomInfo, ROM};
+pub use state::{StateError, STATE_VERSION};
 
 use alloc::rc::Rc;
+use alloc::vec::Vec;
 use core::cell::RefCell;
+use std::convert::TryFrom;
 
 use bus::Bus;
-use cpu::CPU;
+use cartridge::Cartridge;
+use cpu::{Registers, CPU};
+use rom::BOOT_ROM_SIZE;
+use state::{StateReader, StateWriter};
 
 use cpu::Interrupt;
 
 pub struct Gameboy {
     cpu: CPU<Bus>,
+    model: Model,
 }
 
 impl Gameboy {
-    pub fn new(rom: ROM, hal: Rc<RefCell<dyn HAL>>) -> Self {
-        Gameboy {
-            cpu: CPU::new(Bus::with_cartridge(rom.into(), hal)),
+    pub fn new(rom: ROM, hal: Rc<RefCell<dyn HAL>>) -> Result<Self, RomError> {
+        Gameboy::with_model(rom, Model::default(), hal)
+    }
+
+    /// Starts the Gameboy in the state the boot ROM of the given model leaves it in.
+    pub fn with_model(rom: ROM, model: Model, hal: Rc<RefCell<dyn HAL>>) -> Result<Self, RomError> {
+        rom.validate()?;
+
+        let registers = Registers::post_boot(model, rom.header_checksum());
+        let bus = Bus::with_model(Cartridge::try_from(rom)?, model, hal);
+
+        Ok(Gameboy {
+            cpu: CPU::with_registers(bus, registers),
+            model,
+        })
+    }
+
+    /// Starts the Gameboy from power on, running the given boot ROM before
+    /// handing over to the cartridge.
+    pub fn with_boot_rom(
+        rom: ROM,
+        boot_rom: Vec<u8>,
+        hal: Rc<RefCell<dyn HAL>>,
+    ) -> Result<Self, RomError> {
+        rom.validate()?;
+
+        if boot_rom.len() != BOOT_ROM_SIZE {
+            return Err(RomError::InvalidBootRomSize(boot_rom.len()));
         }
+
+        let bus = Bus::with_boot_rom(Cartridge::try_from(rom)?, boot_rom, hal);
+
+        Ok(Gameboy {
+            cpu: CPU::with_registers(bus, Registers::power_on()),
+            model: Model::default(),
+        })
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
     }
 
     pub fn cpu(&self) -> &CPU<Bus> {
         &self.cpu
     }
 
+    pub(crate) fn cpu_mut(&mut self) -> &mut CPU<Bus> {
+        &mut self.cpu
+    }
+
+    /// The last frame drawn by the PPU, the HAL is told when a new one is ready.
+    pub fn frame_buffer(&self) -> &FrameBuffer {
+        self.cpu.bus().ppu().frame_buffer()
+    }
+
+    /// The left and right output of the APU right now, from -1.0 to 1.0.
+    pub fn audio_output(&self) -> (f32, f32) {
+        self.cpu.bus().apu().output()
+    }
+
+    /// The samples made during the last frame, the HAL is told when a new
+    /// frame of them is ready.
+    pub fn audio_buffer(&self) -> &AudioBuffer {
+        self.cpu.bus().apu().audio_buffer()
+    }
+
+    /// Sets the rate the APU's output is resampled to, 48 kHz by default.
+    /// This ends any audio capture that is running.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu
+            .bus_mut()
+            .apu_mut()
+            .set_sample_rate(sample_rate.max(1));
+    }
+
+    /// Starts recording the audio made by each frame from now on, along with
+    /// each channel on its own if `channels` is set.
+    pub fn start_audio_capture(&mut self, channels: bool) {
+        self.cpu.bus_mut().apu_mut().start_capture(channels);
+    }
+
+    /// Stops recording and hands over what was recorded.
+    pub fn stop_audio_capture(&mut self) -> Option<AudioCapture> {
+        self.cpu.bus_mut().apu_mut().stop_capture()
+    }
+
+    pub fn audio_capture(&self) -> Option<&AudioCapture> {
+        self.cpu.bus().apu().capture()
+    }
+
+    /// Takes a channel out of the audio output, and out of the mix captured.
+    pub fn set_channel_muted(&mut self, channel: Channel, muted: bool) {
+        self.cpu.bus_mut().apu_mut().set_muted(channel, muted);
+    }
+
+    /// While any channels are soloed, only they are heard.
+    pub fn set_channel_soloed(&mut self, channel: Channel, soloed: bool) {
+        self.cpu.bus_mut().apu_mut().set_soloed(channel, soloed);
+    }
+
+    /// Turns the high-pass filter of the capacitors on the outputs on or off,
+    /// which takes out the DC offset of the DACs.
+    pub fn set_high_pass_filter(&mut self, enabled: bool) {
+        self.cpu.bus_mut().apu_mut().set_high_pass_filter(enabled);
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.cpu.bus().cartridge().has_battery()
+    }
+
+    pub fn save_ram(&mut self) -> Vec<u8> {
+        let now = self.cpu.bus().unix_time();
+        self.cpu.bus_mut().cartridge_mut().save_ram(now)
+    }
+
+    pub fn load_save_ram(&mut self, data: &[u8]) {
+        let now = self.cpu.bus().unix_time();
+        self.cpu.bus_mut().cartridge_mut().load_save_ram(data, now);
+    }
+
+    pub fn save_ram_dirty(&self) -> bool {
+        self.cpu.bus().cartridge().save_ram_dirty()
+    }
+
+    pub fn clear_save_ram_dirty(&mut self) {
+        self.cpu.bus_mut().cartridge_mut().clear_save_ram_dirty();
+    }
+
+    /// Captures the state of the whole machine. The cartridge ROM is not
+    /// included, so the state can only be loaded back with the same ROM.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        let rom = self.cpu.bus().cartridge().rom();
+
+        state.write_u8(rom.header_checksum());
+        state.write_u16(rom.global_checksum());
+        self.model.save_state(&mut state);
+        self.cpu.save_state(&mut state);
+        self.cpu.bus().save_state(&mut state);
+
+        state.into_bytes()
+    }
+
+    /// Restores a state captured by `save_state`. If the state is invalid
+    /// the machine is left as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let previous = self.save_state();
+
+        let result = self.load_state_unchecked(data);
+
+        if result.is_err() {
+            self.load_state_unchecked(&previous).unwrap();
+        }
+
+        result
+    }
+
+    fn load_state_unchecked(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(data)?;
+        let rom = self.cpu.bus().cartridge().rom();
+
+        if state.read_u8()? != rom.header_checksum() || state.read_u16()? != rom.global_checksum() {
+            return Err(StateError::WrongRom);
+        }
+
+        let model = Model::load_state(&mut state)?;
+        self.cpu.load_state(&mut state)?;
+        self.cpu.bus_mut().load_state(&mut state)?;
+        self.set_model(model);
+
+        Ok(())
+    }
+
+    fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.cpu.bus_mut().ppu_mut().set_model(model);
+    }
+
+    /// Exports the state of the machine in the BESS format, which other emulators can load.
+    pub fn export_bess(&self) -> Vec<u8> {
+        bess::export(&self.cpu, self.model)
+    }
+
+    /// Imports a BESS state, such as one saved by another emulator. Only the
+    /// state BESS records is restored, the rest of the machine carries on as
+    /// it was. If the state is invalid the machine is left as it was.
+    pub fn import_bess(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let previous = self.save_state();
+
+        match bess::import(&mut self.cpu, data) {
+            Ok(model) => {
+                self.set_model(model);
+                Ok(())
+            }
+            Err(err) => {
+                self.load_state_unchecked(&previous).unwrap();
+                Err(err)
+            }
+        }
+    }
+
     pub fn step(&mut self) {
         self.cpu.step();
     }
 
+    /// The buttons the HAL has held down, for `Rewind` to replay.
+    pub(crate) fn joypad_input(&self) -> u8 {
+        self.cpu.bus().joypad().input()
+    }
+
+    pub(crate) fn set_replayed_input(&mut self, input: Option<u8>) {
+        self.cpu.bus_mut().joypad_mut().set_replayed_input(input);
+    }
+
+    /// Runs until the PPU finishes a frame, which happens every 70,224 cycles
+    /// even while the LCD is off.
     pub fn step_frame(&mut self) {
-        let mut was_in_vblank = self.cpu.bus().ppu().in_vblank();
+        let frames = self.cpu.bus().ppu().frames();
 
-        // Run until we are in vblank but were not previously
-        while was_in_vblank || !self.cpu.bus().ppu().in_vblank() {
-            was_in_vblank = self.cpu.bus().ppu().in_vblank();
+        while self.cpu.bus().ppu().frames() == frames {
             self.cpu.step();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::NullHAL;
+
+    fn rom_with_program(program: &[u8], title: &[u8]) -> ROM {
+        let mut data = vec![0; 0x8000];
+        data[0x100..0x100 + program.len()].copy_from_slice(program);
+        data[0x134..0x134 + title.len()].copy_from_slice(title);
+        data[0x14D] = data[0x134..=0x14C]
+            .iter()
+            .fold(0u8, |x, byte| x.wrapping_sub(*byte).wrapping_sub(1));
+
+        ROM::from(data)
+    }
+
+    fn gameboy_with_program(program: &[u8], title: &[u8]) -> Gameboy {
+        let hal = Rc::new(RefCell::new(NullHAL));
+        Gameboy::new(rom_with_program(program, title), hal).unwrap()
+    }
+
+    // LD HL,0xC000; loop: INC A; LD (HL),A; INC L; JR loop
+    const COUNTER_PROGRAM: [u8; 8] = [0x21, 0x00, 0xC0, 0x3C, 0x77, 0x2C, 0x18, 0xFB];
+
+    #[test]
+    fn it_should_resume_from_a_saved_state() {
+        let mut gameboy = gameboy_with_program(&COUNTER_PROGRAM, b"COUNTER");
+
+        for _ in 0..1000 {
+            gameboy.step();
+        }
+
+        let state = gameboy.save_state();
+
+        for _ in 0..500 {
+            gameboy.step();
+        }
+
+        let expected = gameboy.save_state();
+
+        gameboy.load_state(&state).unwrap();
+        assert_eq!(state, gameboy.save_state());
+
+        for _ in 0..500 {
+            gameboy.step();
+        }
+
+        assert_eq!(expected, gameboy.save_state());
+    }
+
+    #[test]
+    fn it_should_reject_states_from_another_rom() {
+        let gameboy = gameboy_with_program(&COUNTER_PROGRAM, b"COUNTER");
+        let mut other = gameboy_with_program(&[0x00, 0x18, 0xFD], b"IDLE");
+
+        assert_eq!(
+            Err(StateError::WrongRom),
+            other.load_state(&gameboy.save_state())
+        );
+    }
+
+    #[test]
+    fn it_should_leave_the_machine_untouched_when_a_state_is_truncated() {
+        let mut gameboy = gameboy_with_program(&COUNTER_PROGRAM, b"COUNTER");
+        let state = gameboy.save_state();
+
+        for _ in 0..100 {
+            gameboy.step();
+        }
+
+        let before = gameboy.save_state();
+
+        assert_eq!(
+            Err(StateError::UnexpectedEnd),
+            gameboy.load_state(&state[..state.len() - 1])
+        );
+        assert_eq!(before, gameboy.save_state());
+    }
+
+    #[test]
+    fn it_should_round_trip_through_bess() {
+        let mut gameboy = gameboy_with_program(&COUNTER_PROGRAM, b"COUNTER");
+
+        for _ in 0..1000 {
+            gameboy.step();
+        }
+
+        let state = gameboy.export_bess();
+        let mut imported = gameboy_with_program(&COUNTER_PROGRAM, b"COUNTER");
+        imported.import_bess(&state).unwrap();
+
+        let (expected, actual) = (gameboy.cpu(), imported.cpu());
+        assert_eq!(expected.registers().pc(), actual.registers().pc());
+        assert_eq!(expected.registers().af(), actual.registers().af());
+        assert_eq!(expected.registers().hl(), actual.registers().hl());
+        assert_eq!(expected.bus().wram(), actual.bus().wram());
+        assert_eq!(
+            expected.bus().io_registers()[..],
+            actual.bus().io_registers()[..]
+        );
+        assert_eq!(state, imported.export_bess());
+    }
+
+    #[test]
+    fn it_should_reject_bess_states_without_a_footer() {
+        let mut gameboy = gameboy_with_program(&COUNTER_PROGRAM, b"COUNTER");
+        let state = gameboy.export_bess();
+
+        assert_eq!(
+            Err(StateError::InvalidMagic),
+            gameboy.import_bess(&state[..state.len() - 1])
+        );
+        assert_eq!(
+            Err(StateError::WrongRom),
+            gameboy_with_program(&COUNTER_PROGRAM, b"OTHER").import_bess(&state)
+        );
+    }
+
+    #[test]
+    fn it_should_step_back_frame_by_frame() {
+        let mut gameboy = gameboy_with_program(&COUNTER_PROGRAM, b"COUNTER");
+        let mut rewind = Rewind::new(4, usize::MAX);
+        let mut states = Vec::new();
+
+        for _ in 0..10 {
+            rewind.step_frame(&mut gameboy);
+            states.push(gameboy.save_state());
+        }
+
+        assert_eq!(2, rewind.len());
+
+        for frame in (4..10).rev() {
+            assert!(rewind.step_back(&mut gameboy));
+            assert_eq!(frame, rewind.frame());
+            assert_eq!(states[frame as usize - 1], gameboy.save_state());
+        }
+
+        assert!(!rewind.step_back(&mut gameboy));
+    }
+
+    #[test]
+    fn it_should_replay_the_recorded_input_when_stepping_back() {
+        struct InputHAL(bool);
+
+        impl HAL for InputHAL {
+            fn is_joypad_pressed(&self, button: Joypad) -> bool {
+                self.0 && button == Joypad::A
+            }
+
+            fn serial_callback(&mut self, _: u8) -> u8 {
+                0xFF
+            }
+        }
+
+        // loop: LD A,0x10; LDH (0x00),A; LDH A,(0x00); LD HL,0xC000;
+        // ADD A,(HL); LD (HL),A; JR loop
+        let program = [
+            0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0x21, 0x00, 0xC0, 0x86, 0x77, 0x18, 0xF3,
+        ];
+        let hal = Rc::new(RefCell::new(InputHAL(false)));
+        let mut gameboy = Gameboy::new(rom_with_program(&program, b"INPUT"), hal.clone()).unwrap();
+        let mut rewind = Rewind::new(4, usize::MAX);
+        let mut states = Vec::new();
+
+        // A is held down for two of the frames after the second snapshot
+        for frame in 0..8 {
+            hal.borrow_mut().0 = frame == 4 || frame == 5;
+            rewind.step_frame(&mut gameboy);
+            states.push(gameboy.save_state());
+        }
+
+        for frame in (4..8).rev() {
+            assert!(rewind.step_back(&mut gameboy));
+            assert_eq!(states[frame - 1], gameboy.save_state());
+        }
+    }
+
+    #[test]
+    fn it_should_drop_the_oldest_snapshots_to_stay_under_the_memory_cap() {
+        let mut gameboy = gameboy_with_program(&COUNTER_PROGRAM, b"COUNTER");
+        let max_bytes = gameboy.save_state().len() + 1024;
+        let mut rewind = Rewind::new(1, max_bytes);
+
+        for _ in 0..100 {
+            rewind.step_frame(&mut gameboy);
+        }
+
+        assert!(rewind.memory_usage() <= max_bytes);
+        assert!(rewind.len() > 1 && rewind.len() < 100);
+    }
+
+    #[test]
+    fn it_should_notify_the_hal_once_per_frame() {
+        struct CountingHAL(usize);
+
+        impl HAL for CountingHAL {
+            fn is_joypad_pressed(&self, _: Joypad) -> bool {
+                false
+            }
+
+            fn serial_callback(&mut self, _: u8) -> u8 {
+                0xFF
+            }
+
+            fn frame_ready(&mut self, _: &FrameBuffer) {
+                self.0 += 1;
+            }
+        }
+
+        let mut data = vec![0; 0x8000];
+        data[0x100..0x100 + COUNTER_PROGRAM.len()].copy_from_slice(&COUNTER_PROGRAM);
+
+        let hal = Rc::new(RefCell::new(CountingHAL(0)));
+        let mut gameboy = Gameboy::new(ROM::from(data), hal.clone()).unwrap();
+
+        for _ in 0..3 {
+            gameboy.step_frame();
+        }
+
+        assert_eq!(3, hal.borrow().0);
+    }
+
+    #[test]
+    fn it_should_step_frames_while_the_lcd_is_off() {
+        // LD A,0; LDH (0x40),A; JR -2
+        let mut gameboy = gameboy_with_program(&[0x3E, 0x00, 0xE0, 0x40, 0x18, 0xFE], b"LCD OFF");
+
+        for _ in 0..3 {
+            gameboy.step_frame();
+        }
+
+        assert!(gameboy
+            .frame_buffer()
+            .pixels()
+            .iter()
+            .all(|&color| color == Color::White));
+    }
+
+    #[test]
+    fn it_should_resample_a_frame_of_audio() {
+        // JR -2
+        let mut gameboy = gameboy_with_program(&[0x18, 0xFE], b"AUDIO");
+        gameboy.set_sample_rate(44100);
+
+        for _ in 0..30 {
+            gameboy.step_frame();
+        }
+
+        let audio_buffer = gameboy.audio_buffer();
+
+        // 70,224 cycles at 44.1 kHz is a little over 738 samples
+        assert_eq!(44100, audio_buffer.sample_rate());
+        assert!((738..=739).contains(&audio_buffer.len()));
+
+        // The first pulse channel's DAC is left on after the boot ROM, but the
+        // high-pass filter takes its offset out
+        assert!(audio_buffer
+            .samples()
+            .iter()
+            .all(|sample| sample.abs() < 0.01));
+    }
+
+    #[test]
+    fn it_should_capture_each_channel() {
+        // JR -2
+        let mut gameboy = gameboy_with_program(&[0x18, 0xFE], b"CAPTURE");
+        gameboy.start_audio_capture(true);
+
+        for _ in 0..2 {
+            gameboy.step_frame();
+        }
+
+        let capture = gameboy.stop_audio_capture().unwrap();
+        let frames = capture.mix().len() / 2;
+
+        assert!(frames > 1500 && frames < 1700);
+
+        for &channel in &[
+            Channel::Pulse1,
+            Channel::Pulse2,
+            Channel::Wave,
+            Channel::Noise,
+        ] {
+            let samples = capture.channel(channel).unwrap();
+
+            assert!((samples.len() as isize - frames as isize).abs() <= 1);
+        }
+
+        assert!(gameboy.audio_capture().is_none());
+    }
+
+    #[test]
+    fn it_should_reset_the_apu_when_loading_a_state_without_it() {
+        // JR -2
+        let mut gameboy = gameboy_with_program(&[0x18, 0xFE], b"AUDIO");
+        gameboy.set_sample_rate(44100);
+
+        // A version 7 state is the same, without the APU at the end
+        let header_len = StateWriter::new().into_bytes().len();
+        let mut apu = StateWriter::new();
+        gameboy.cpu.bus().apu().save_state(&mut apu);
+
+        let mut state = gameboy.save_state();
+        state.truncate(state.len() - (apu.into_bytes().len() - header_len));
+        state[header_len - 4..header_len].copy_from_slice(&7u32.to_le_bytes());
+
+        gameboy.load_state(&state).unwrap();
+        assert_eq!(0x70, gameboy.cpu.bus().apu().read(0xFF26));
+
+        // The first frame after the boot ROM is a short one
+        gameboy.step_frame();
+        gameboy.step_frame();
+        assert_eq!(44100, gameboy.audio_buffer().sample_rate());
+        assert!((738..=739).contains(&gameboy.audio_buffer().len()));
+    }
+
+    #[test]
+    fn it_should_apply_the_model_of_a_loaded_state() {
+        let gameboy_with_model = |model| {
+            let rom = rom_with_program(&COUNTER_PROGRAM, b"COUNTER");
+            Gameboy::with_model(rom, model, Rc::new(RefCell::new(NullHAL))).unwrap()
+        };
+
+        let dmg = gameboy_with_model(Model::DMG);
+        let cgb = gameboy_with_model(Model::CGB);
+
+        // Both start in VBlank, where the DMG's STAT write bug interrupts
+        let mut gameboy = gameboy_with_model(Model::DMG);
+        gameboy.import_bess(&cgb.export_bess()).unwrap();
+        assert_eq!(Model::CGB, gameboy.model());
+        assert!(!gameboy.cpu.bus_mut().ppu_mut().set_stat(0x00));
+
+        gameboy.load_state(&dmg.save_state()).unwrap();
+        assert_eq!(Model::DMG, gameboy.model());
+        assert!(gameboy.cpu.bus_mut().ppu_mut().set_stat(0x00));
+    }
+
+    #[test]
+    fn it_should_start_the_ppu_where_each_boot_rom_leaves_it() {
+        // LY, STAT and the M-cycles of NOPs until the first frame starts. The
+        // monochrome models hand over on line 153, which LY already reads as 0
+        let models = [
+            (Model::DMG0, 145, 0x81, 926),
+            (Model::DMG, 0, 0x85, 14),
+            (Model::MGB, 0, 0x85, 14),
+            (Model::SGB, 0, 0x85, 14),
+            (Model::SGB2, 0, 0x85, 14),
+            (Model::CGB, 144, 0x81, 1031),
+            (Model::AGB, 144, 0x81, 1031),
+        ];
+
+        for &(model, ly, stat, m_cycles) in &models {
+            let hal = Rc::new(RefCell::new(NullHAL));
+            let mut gameboy = Gameboy::with_model(ROM::from(vec![0; 0x8000]), model, hal).unwrap();
+
+            let ppu = gameboy.cpu().bus().ppu();
+            let (lcdc, stat_read): (u8, u8) = (ppu.lcdc().into(), ppu.stat().into());
+
+            assert_eq!(0x91, lcdc, "{}", model);
+            assert_eq!(stat, stat_read, "{}", model);
+            assert_eq!(ly, ppu.ly(), "{}", model);
+
+            for _ in 0..m_cycles {
+                assert!(gameboy.cpu().bus().ppu().in_vblank(), "{}", model);
+                gameboy.step();
+            }
+
+            assert!(!gameboy.cpu().bus().ppu().in_vblank(), "{}", model);
+        }
+    }
+}
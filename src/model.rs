@@ -0,0 +1,59 @@
+use std::fmt;
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// The hardware model being emulated. The models differ in the state the boot
+/// ROM leaves the CPU and IO registers in, which some games use to detect
+/// the hardware they are running on.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Model {
+    DMG0,
+    #[default]
+    DMG,
+    MGB,
+    SGB,
+    SGB2,
+    CGB,
+    AGB,
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Model::DMG0 => write!(f, "DMG0 (early Game Boy)"),
+            Model::DMG => write!(f, "DMG (Game Boy)"),
+            Model::MGB => write!(f, "MGB (Game Boy Pocket)"),
+            Model::SGB => write!(f, "SGB (Super Game Boy)"),
+            Model::SGB2 => write!(f, "SGB2 (Super Game Boy 2)"),
+            Model::CGB => write!(f, "CGB (Game Boy Color)"),
+            Model::AGB => write!(f, "AGB (Game Boy Advance)"),
+        }
+    }
+}
+
+impl Model {
+    pub fn save_state(self, state: &mut StateWriter) {
+        state.write_u8(match self {
+            Model::DMG0 => 0,
+            Model::DMG => 1,
+            Model::MGB => 2,
+            Model::SGB => 3,
+            Model::SGB2 => 4,
+            Model::CGB => 5,
+            Model::AGB => 6,
+        });
+    }
+
+    pub fn load_state(state: &mut StateReader) -> Result<Self, StateError> {
+        match state.read_u8()? {
+            0 => Ok(Model::DMG0),
+            1 => Ok(Model::DMG),
+            2 => Ok(Model::MGB),
+            3 => Ok(Model::SGB),
+            4 => Ok(Model::SGB2),
+            5 => Ok(Model::CGB),
+            6 => Ok(Model::AGB),
+            _ => Err(StateError::InvalidValue("model")),
+        }
+    }
+}
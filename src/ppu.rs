@@ -1,12 +1,17 @@
+use alloc::collections::VecDeque;
 use alloc::rc::Rc;
 use bitfield::bitfield;
 use bitfield::Bit;
 use bitfield::BitRange;
 use core::cell::RefCell;
+use core::mem;
 use std::fmt;
 
+use super::frame_buffer::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
 use super::hal::Color;
 use super::hal::HAL;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::Model;
 
 pub enum BackgroundTileData {
     _8800,
@@ -94,6 +99,17 @@ impl From<u8> for Mode {
     }
 }
 
+impl From<&Mode> for u8 {
+    fn from(value: &Mode) -> u8 {
+        match value {
+            Mode::HBlank => 0,
+            Mode::VBlank => 1,
+            Mode::OAMRead => 2,
+            Mode::VRAMRead => 3,
+        }
+    }
+}
+
 impl fmt::Display for Mode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
@@ -228,6 +244,171 @@ impl Into<u8> for OBP {
     }
 }
 
+bitfield! {
+    #[derive(Clone, Copy)]
+    pub struct OBJAttributes(u8);
+    //impl Debug;
+    u8;
+    pub bg_priority, _: 7;
+    pub y_flip, _: 6;
+    pub x_flip, _: 5;
+    pub palette, _: 4;
+}
+
+#[derive(Clone, Copy)]
+struct Sprite {
+    y: u8,
+    x: u8,
+    tile_index: u8,
+    attributes: OBJAttributes,
+}
+
+impl From<&[u8]> for Sprite {
+    fn from(value: &[u8]) -> Self {
+        Sprite {
+            y: value[0],
+            x: value[1],
+            tile_index: value[2],
+            attributes: OBJAttributes(value[3]),
+        }
+    }
+}
+
+impl Sprite {
+    fn to_bytes(self) -> [u8; 4] {
+        [self.y, self.x, self.tile_index, self.attributes.0]
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SpritePixel {
+    color: u8,
+    palette: bool,
+    bg_priority: bool,
+}
+
+impl From<u8> for SpritePixel {
+    fn from(value: u8) -> Self {
+        SpritePixel {
+            color: value & 0x03,
+            palette: value.bit(2),
+            bg_priority: value.bit(3),
+        }
+    }
+}
+
+impl From<SpritePixel> for u8 {
+    fn from(value: SpritePixel) -> u8 {
+        value.color | (value.palette as u8) << 2 | (value.bg_priority as u8) << 3
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+impl From<u8> for FetcherStep {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => FetcherStep::Tile,
+            1 => FetcherStep::DataLow,
+            2 => FetcherStep::DataHigh,
+            3 => FetcherStep::Push,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<FetcherStep> for u8 {
+    fn from(value: FetcherStep) -> u8 {
+        match value {
+            FetcherStep::Tile => 0,
+            FetcherStep::DataLow => 1,
+            FetcherStep::DataHigh => 2,
+            FetcherStep::Push => 3,
+        }
+    }
+}
+
+/// Fetches a row of 8 background or window pixels at a time for the
+/// background FIFO during mode 3.
+struct Fetcher {
+    step: FetcherStep,
+    dots: u8,
+    window: bool,
+    first_fetch: bool,
+    tile_x: u8,
+    tile_index: u8,
+    data_low: u8,
+    data_high: u8,
+}
+
+impl Fetcher {
+    fn new() -> Self {
+        Fetcher {
+            step: FetcherStep::Tile,
+            dots: 0,
+            window: false,
+            first_fetch: true,
+            tile_x: 0,
+            tile_index: 0,
+            data_low: 0,
+            data_high: 0,
+        }
+    }
+
+    /// Switches to fetching from the start of the window's current line.
+    fn start_window(&mut self) {
+        self.step = FetcherStep::Tile;
+        self.dots = 0;
+        self.window = true;
+        self.tile_x = 0;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.step.into());
+        state.write_u8(self.dots);
+        state.write_bool(self.window);
+        state.write_bool(self.first_fetch);
+        state.write_u8(self.tile_x);
+        state.write_u8(self.tile_index);
+        state.write_u8(self.data_low);
+        state.write_u8(self.data_high);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.step = match state.read_u8()? {
+            step @ 0..=3 => FetcherStep::from(step),
+            _ => return Err(StateError::InvalidValue("PPU fetcher step")),
+        };
+        self.dots = state.read_u8()?;
+        self.window = state.read_bool()?;
+        self.first_fetch = state.read_bool()?;
+        self.tile_x = state.read_u8()?;
+        self.tile_index = state.read_u8()?;
+        self.data_low = state.read_u8()?;
+        self.data_high = state.read_u8()?;
+
+        Ok(())
+    }
+}
+
+/// Decodes one row of a tile into palette indices, leftmost pixel first.
+fn decode_tile_row(low: u8, high: u8) -> [u8; 8] {
+    let mut pixels = [0; 8];
+
+    for (x, pixel) in pixels.iter_mut().enumerate() {
+        pixel.set_bit(0, low.bit(7 - x));
+        pixel.set_bit(1, high.bit(7 - x));
+    }
+
+    pixels
+}
+
 impl From<u8> for Color {
     fn from(value: u8) -> Self {
         match value {
@@ -256,9 +437,39 @@ pub struct PPU {
     obp0: OBP,
     obp1: OBP,
 
-    counter: usize,
+    // The position within the current line, from 0 to 455
+    dot: usize,
     mode: Mode,
 
+    // Mode 3 draws pixels from a FIFO of background or window pixels mixed
+    // with a FIFO of sprite pixels, pausing while sprites are fetched
+    line_sprites: Vec<Sprite>,
+    fetcher: Fetcher,
+    background_fifo: VecDeque<u8>,
+    sprite_fifo: VecDeque<SpritePixel>,
+    fetching_sprite: Option<(Sprite, u8)>,
+    lcd_x: u8,
+    discard: u8,
+
+    // The window keeps its own line counter which only advances on lines
+    // where it was drawn, and is only drawn once LY has matched WY
+    window_line: u8,
+    window_y_triggered: bool,
+    window_fills_next_line: bool,
+
+    // Every STAT interrupt source is ORed into a single line, which only
+    // interrupts when it goes from low to high
+    stat_line: bool,
+    stat_write_bug: bool,
+
+    // The LCD keeps to the same frame timing while it's off, and the first
+    // frame after turning it back on isn't shown
+    lcd_off_dot: usize,
+    skip_frame: bool,
+    frames: u64,
+
+    frame_buffer: FrameBuffer,
+
     hal: Rc<RefCell<dyn HAL>>,
 }
 
@@ -280,17 +491,91 @@ impl PPU {
             obp0: OBP(0xFF),
             obp1: OBP(0xFF),
 
-            counter: 0,
+            dot: 0,
             mode: Mode::OAMRead,
 
+            line_sprites: Vec::new(),
+            fetcher: Fetcher::new(),
+            background_fifo: VecDeque::new(),
+            sprite_fifo: VecDeque::new(),
+            fetching_sprite: None,
+            lcd_x: 0,
+            discard: 0,
+
+            window_line: 0,
+            window_y_triggered: false,
+            window_fills_next_line: false,
+
+            stat_line: false,
+            stat_write_bug: true,
+
+            lcd_off_dot: 0,
+            skip_frame: false,
+            frames: 0,
+
+            frame_buffer: FrameBuffer::new(),
+
             hal,
         }
     }
 
+    /// The state each model's boot ROM leaves the PPU in, with the LCD on and
+    /// part way through VBlank. The first frame is cut short by however much
+    /// of it the boot ROM has already used.
+    pub fn post_boot(model: Model, hal: Rc<RefCell<dyn HAL>>) -> Self {
+        // The line and the dot into it that each boot ROM hands over on
+        let (ly, dot) = match model {
+            Model::DMG0 => (145, 400),
+            Model::DMG | Model::MGB | Model::SGB | Model::SGB2 => (153, 400),
+            Model::CGB | Model::AGB => (144, 436),
+        };
+
+        let mut ppu = PPU {
+            ly,
+            dot,
+            mode: Mode::VBlank,
+            ..PPU::new(hal)
+        };
+
+        ppu.set_model(model);
+
+        // LYC is 0, which LY already reads as on the DMG's line 153
+        ppu.stat.set_coincidence_flag(ppu.ly() == ppu.lyc);
+        ppu.stat_line = ppu.stat_line();
+        ppu
+    }
+
+    /// Applies the differences between the models, writing to STAT only
+    /// triggers spurious interrupts on the monochrome models.
+    pub fn set_model(&mut self, model: Model) {
+        self.stat_write_bug = !matches!(model, Model::CGB | Model::AGB);
+    }
+
+    /// The state of the PPU at power on, the boot ROM is responsible for
+    /// setting up the palette and turning the LCD on.
+    pub fn power_on(hal: Rc<RefCell<dyn HAL>>) -> Self {
+        PPU {
+            lcdc: LCDC(0x00),
+            bgp: BGP(0x00),
+            mode: Mode::HBlank,
+            ..PPU::new(hal)
+        }
+    }
+
+    pub fn frame_buffer(&self) -> &FrameBuffer {
+        &self.frame_buffer
+    }
+
     pub fn in_vblank(&self) -> bool {
         self.mode == Mode::VBlank
     }
 
+    /// The number of frames finished, which carries on going up every 70,224
+    /// dots while the LCD is off.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
             0x8000..=0x9FFF => {
@@ -311,8 +596,20 @@ impl PPU {
         }
     }
 
-    pub fn dma(&mut self, data: [u8; 160]) {
-        self.oam = data;
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    pub fn vram_mut(&mut self) -> &mut [u8] {
+        &mut self.vram
+    }
+
+    pub fn oam(&self) -> &[u8] {
+        &self.oam
+    }
+
+    pub fn oam_mut(&mut self) -> &mut [u8] {
+        &mut self.oam
     }
 
     pub fn write(&mut self, addr: u16, value: u8) {
@@ -340,7 +637,10 @@ impl PPU {
     }
 
     pub fn stat(&self) -> STAT {
-        self.stat.clone()
+        let mut stat = self.stat.0 | 0x80;
+        stat.set_bit_range(1, 0, u8::from(&self.mode));
+
+        STAT(stat)
     }
 
     pub fn scy(&self) -> u8 {
@@ -352,10 +652,11 @@ impl PPU {
     }
 
     pub fn ly(&self) -> u8 {
-        if self.lcdc.lcd_enabled() {
-            self.ly
-        } else {
-            0
+        match self.ly {
+            _ if !self.lcdc.lcd_enabled() => 0,
+            // LY already reads 0 after the first M-cycle of the last line
+            153 if self.dot >= 4 => 0,
+            ly => ly,
         }
     }
 
@@ -384,14 +685,59 @@ impl PPU {
     }
 
     pub fn set_lcdc(&mut self, value: u8) {
+        let was_enabled = self.lcdc.lcd_enabled();
         self.lcdc = LCDC(value);
+
+        match (was_enabled, self.lcdc.lcd_enabled()) {
+            // The screen goes blank and LY stays at 0 in mode 0 until it's turned back on
+            (true, false) => {
+                self.lcd_off_dot = usize::from(self.ly) * 456 + self.dot;
+
+                self.ly = 0;
+                self.dot = 0;
+                self.mode = Mode::HBlank;
+                self.stat_line = false;
+
+                self.window_line = 0;
+                self.window_y_triggered = false;
+                self.window_fills_next_line = false;
+
+                self.frame_buffer.clear();
+            }
+            // Drawing starts again from the top of the screen, but the first
+            // line starts in mode 0 rather than looking for sprites
+            (false, true) => {
+                self.ly = 0;
+                self.dot = 0;
+                self.mode = Mode::HBlank;
+                self.skip_frame = true;
+            }
+            _ => {}
+        }
     }
 
-    pub fn set_stat(&mut self, value: u8) {
+    /// Writes the interrupt enables, returning true if the write causes a STAT
+    /// interrupt. On the monochrome models every source is briefly enabled by
+    /// the write, which interrupts in HBlank, VBlank or while LY=LYC.
+    pub fn set_stat(&mut self, value: u8) -> bool {
         let mut stat = self.stat.0;
         stat.set_bit_range(6, 3, BitRange::<u8>::bit_range(&value, 6, 3));
 
+        let interrupt = self.stat_write_bug
+            && self.lcdc.lcd_enabled()
+            && !self.stat_line
+            && (self.mode == Mode::HBlank
+                || self.mode == Mode::VBlank
+                || self.stat.coincidence_flag());
+
+        if interrupt {
+            // The line is then low again from the next dot if nothing else holds it high
+            self.stat_line = true;
+        }
+
         self.stat = STAT(stat);
+
+        interrupt
     }
 
     pub fn set_scy(&mut self, value: u8) {
@@ -430,85 +776,291 @@ impl PPU {
         self.obp1 = OBP(value);
     }
 
+    /// Restores 0xFF40-0xFF4B without the side effects of writing to them. The
+    /// position within the scanline is not known so it restarts at the
+    /// beginning of the mode in STAT.
+    pub fn restore_registers(&mut self, registers: &[u8]) {
+        self.lcdc = LCDC(registers[0x0]);
+        self.stat = STAT(registers[0x1]);
+        self.scy = registers[0x2];
+        self.scx = registers[0x3];
+        self.ly = registers[0x4];
+        self.lyc = registers[0x5];
+        // 0xFF46 DMA
+        self.bgp = BGP(registers[0x7]);
+        self.obp0 = OBP(registers[0x8]);
+        self.obp1 = OBP(registers[0x9]);
+        self.wy = registers[0xA];
+        self.wx = registers[0xB];
+
+        self.mode = self.stat.mode_flag();
+        self.resume_mode(0);
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.vram);
+        state.write_bytes(&self.oam);
+
+        state.write_u8(self.lcdc.0);
+        state.write_u8(self.stat.0);
+        state.write_u8(self.scy);
+        state.write_u8(self.scx);
+        state.write_u8(self.ly);
+        state.write_u8(self.lyc);
+        state.write_u8(self.wy);
+        state.write_u8(self.wx);
+        state.write_u8(self.bgp.0);
+        state.write_u8(self.obp0.0);
+        state.write_u8(self.obp1.0);
+
+        state.write_u32(self.dot as u32);
+        state.write_u8(u8::from(&self.mode));
+
+        state.write_bytes(&self.frame_buffer.shades());
+
+        state.write_u8(self.window_line);
+        state.write_bool(self.window_y_triggered);
+        state.write_bool(self.window_fills_next_line);
+
+        let line_sprites: Vec<u8> = self
+            .line_sprites
+            .iter()
+            .flat_map(|sprite| sprite.to_bytes().to_vec())
+            .collect();
+        state.write_bytes(&line_sprites);
+
+        self.fetcher.save_state(state);
+        state.write_bytes(&self.background_fifo.iter().cloned().collect::<Vec<u8>>());
+        state.write_bytes(
+            &self
+                .sprite_fifo
+                .iter()
+                .map(|&pixel| u8::from(pixel))
+                .collect::<Vec<u8>>(),
+        );
+
+        match &self.fetching_sprite {
+            Some((sprite, dots)) => {
+                state.write_bytes(&sprite.to_bytes());
+                state.write_u8(*dots);
+            }
+            None => {
+                state.write_bytes(&[]);
+                state.write_u8(0);
+            }
+        }
+
+        state.write_u8(self.lcd_x);
+        state.write_u8(self.discard);
+
+        state.write_bool(self.stat_line);
+
+        state.write_u32(self.lcd_off_dot as u32);
+        state.write_bool(self.skip_frame);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes_into(&mut self.vram, "VRAM")?;
+        state.read_bytes_into(&mut self.oam, "OAM")?;
+
+        self.lcdc = LCDC(state.read_u8()?);
+        self.stat = STAT(state.read_u8()?);
+        self.scy = state.read_u8()?;
+        self.scx = state.read_u8()?;
+        self.ly = state.read_u8()?;
+        self.lyc = state.read_u8()?;
+        self.wy = state.read_u8()?;
+        self.wx = state.read_u8()?;
+        self.bgp = BGP(state.read_u8()?);
+        self.obp0 = OBP(state.read_u8()?);
+        self.obp1 = OBP(state.read_u8()?);
+
+        let dot = state.read_u32()? as usize;
+        self.mode = match state.read_u8()? {
+            mode @ 0..=3 => Mode::from(mode),
+            _ => return Err(StateError::InvalidValue("PPU mode")),
+        };
+
+        self.frame_buffer.clear();
+
+        if state.version() >= 2 {
+            let shades = state.read_bytes()?;
+
+            if shades.len() != SCREEN_WIDTH * SCREEN_HEIGHT || shades.iter().any(|&shade| shade > 3)
+            {
+                return Err(StateError::InvalidValue("frame buffer"));
+            }
+
+            for (i, &shade) in shades.iter().enumerate() {
+                self.frame_buffer
+                    .set_pixel(i % SCREEN_WIDTH, i / SCREEN_WIDTH, Color::from(shade));
+            }
+        }
+
+        if state.version() >= 3 {
+            self.window_line = state.read_u8()?;
+            self.window_y_triggered = state.read_bool()?;
+            self.window_fills_next_line = state.read_bool()?;
+        } else {
+            self.window_line = 0;
+            self.window_y_triggered = self.ly >= self.wy;
+            self.window_fills_next_line = false;
+        }
+
+        // Older versions counted dots from the start of each mode and didn't
+        // keep the state of mode 3
+        if state.version() < 4 {
+            self.resume_mode(dot);
+            self.stat_line = self.stat_line();
+            self.lcd_off_dot = 0;
+            self.skip_frame = false;
+            return Ok(());
+        }
+
+        if dot >= 456 {
+            return Err(StateError::InvalidValue("PPU dot"));
+        }
+
+        self.dot = dot;
+
+        let line_sprites = state.read_bytes()?;
+
+        if line_sprites.len() % 4 != 0 || line_sprites.len() > 10 * 4 {
+            return Err(StateError::InvalidValue("PPU line sprites"));
+        }
+
+        self.line_sprites = line_sprites.chunks(4).map(Sprite::from).collect();
+
+        self.fetcher.load_state(state)?;
+
+        let background_fifo = state.read_bytes()?;
+        let sprite_fifo = state.read_bytes()?;
+
+        if background_fifo.len() > 8 || background_fifo.iter().any(|&pixel| pixel > 3) {
+            return Err(StateError::InvalidValue("PPU background FIFO"));
+        }
+
+        if sprite_fifo.len() > 8 || sprite_fifo.iter().any(|&pixel| pixel > 0x0F) {
+            return Err(StateError::InvalidValue("PPU sprite FIFO"));
+        }
+
+        self.background_fifo = background_fifo.iter().cloned().collect();
+        self.sprite_fifo = sprite_fifo.iter().map(|&pixel| pixel.into()).collect();
+
+        let fetching_sprite = state.read_bytes()?;
+        let dots = state.read_u8()?;
+
+        self.fetching_sprite = match fetching_sprite.len() {
+            0 => None,
+            4 => Some((Sprite::from(fetching_sprite), dots)),
+            _ => return Err(StateError::InvalidValue("PPU sprite fetch")),
+        };
+
+        self.lcd_x = state.read_u8()?;
+        self.discard = state.read_u8()?;
+
+        if usize::from(self.lcd_x) > SCREEN_WIDTH {
+            return Err(StateError::InvalidValue("PPU pixel position"));
+        }
+
+        self.stat_line = if state.version() >= 5 {
+            state.read_bool()?
+        } else {
+            self.stat_line()
+        };
+
+        if state.version() >= 6 {
+            self.lcd_off_dot = state.read_u32()? as usize;
+            self.skip_frame = state.read_bool()?;
+
+            if self.lcd_off_dot >= 154 * 456 {
+                return Err(StateError::InvalidValue("PPU LCD off dot"));
+            }
+        } else {
+            self.lcd_off_dot = 0;
+            self.skip_frame = false;
+        }
+
+        Ok(())
+    }
+
     pub fn tick(&mut self) -> (bool, bool) {
         if !self.lcdc.lcd_enabled() {
+            self.lcd_off_dot = (self.lcd_off_dot + 1) % (154 * 456);
+
+            if self.lcd_off_dot == 144 * 456 {
+                self.frames += 1;
+                self.hal.borrow_mut().frame_ready(&self.frame_buffer);
+            }
+
             return (false, false);
         }
 
-        self.counter = self.counter.wrapping_add(1);
-
-        let (mut vblank, mut lcdstat) = (false, false);
+        let mut vblank = false;
 
         match self.mode {
             Mode::OAMRead => {
-                if self.counter == 80 {
-                    self.counter = 0;
+                // One OAM entry is checked every other dot
+                if self.dot.is_multiple_of(2) {
+                    self.scan_oam_entry(self.dot / 2);
+                }
+
+                if self.dot == 79 {
                     self.mode = Mode::VRAMRead;
+                    self.start_pixel_transfer();
                 }
             }
             Mode::VRAMRead => {
-                // draw individual pixels instead?
-
-                if self.counter == 172 {
-                    self.counter = 0;
+                if self.tick_pixel_transfer() {
                     self.mode = Mode::HBlank;
 
-                    if self.stat.hblank_interrupt_enabled() {
-                        lcdstat = true;
+                    if self.fetcher.window {
+                        self.window_line = self.window_line.wrapping_add(1);
                     }
-
-                    self.render_scanline();
                 }
             }
-            Mode::HBlank => {
-                if self.counter == 204 {
-                    self.counter = 0;
-                    self.ly = self.ly.wrapping_add(1);
-                    self.stat.set_coincidence_flag(self.ly == self.lyc);
-
-                    if self.ly == 143 {
-                        self.mode = Mode::VBlank;
-                        vblank = true;
-
-                        if self.stat.vblank_interrupt_enabled() {
-                            lcdstat = true;
-                        }
-                    } else {
-                        self.mode = Mode::OAMRead;
-
-                        if self.stat.oam_interrupt_enabled() {
-                            lcdstat = true;
-                        }
-                    }
-                }
-
-                if self.stat.coincidence_interrupt_enabled() && self.stat.coincidence_flag() {
-                    lcdstat = true;
-                }
+            Mode::HBlank if self.ly == 0 && self.dot == 79 => {
+                self.line_sprites.clear();
+                self.mode = Mode::VRAMRead;
+                self.start_pixel_transfer();
             }
-            Mode::VBlank => {
-                if self.counter == 456 {
-                    self.counter = 0;
-                    self.ly = self.ly.wrapping_add(1);
-                    self.stat.set_coincidence_flag(self.ly == self.lyc);
+            Mode::HBlank | Mode::VBlank => {}
+        }
 
-                    if self.ly > 153 {
-                        self.mode = Mode::OAMRead;
+        self.dot += 1;
 
-                        if self.stat.oam_interrupt_enabled() {
-                            lcdstat = true;
-                        }
+        if self.dot == 456 {
+            self.dot = 0;
+            self.ly = self.ly.wrapping_add(1);
 
-                        self.ly = 0;
-                    }
+            if self.ly == 144 {
+                self.mode = Mode::VBlank;
+                vblank = true;
+
+                if mem::take(&mut self.skip_frame) {
+                    self.frame_buffer.clear();
                 }
 
-                if self.stat.coincidence_interrupt_enabled() && self.stat.coincidence_flag() {
-                    lcdstat = true;
+                self.frames += 1;
+                self.hal.borrow_mut().frame_ready(&self.frame_buffer);
+            } else if self.ly > 153 || self.mode == Mode::HBlank {
+                if self.ly > 153 {
+                    self.ly = 0;
+                    self.window_line = 0;
+                    self.window_y_triggered = false;
                 }
+
+                self.mode = Mode::OAMRead;
             }
         }
 
+        // LYC is compared against LY as it reads, so it matches 0 on line 153
+        self.stat.set_coincidence_flag(self.ly() == self.lyc);
+
+        let stat_line = self.stat_line();
+        let lcdstat = stat_line && !self.stat_line;
+        self.stat_line = stat_line;
+
         (vblank, lcdstat)
     }
 
@@ -548,14 +1100,8 @@ impl PPU {
         for tile_map_row in 0..32 {
             for tile_map_column in 0..32 {
                 let tile_map_offset = tile_map_row * 32 + tile_map_column;
-                let tile_index =
-                    self.vram[background_tile_map_vram_offset + tile_map_offset] as usize;
-
-                if let BackgroundTileData::_8800 =
-                    self.lcdc.background_and_window_tile_data_select()
-                {
-                    return [Color::White; 32 * 8 * 32 * 8].to_vec(); //unimplemented!();
-                }
+                let tile_index = self.vram[background_tile_map_vram_offset + tile_map_offset];
+                let tile_index = self.tile_number(tile_index);
 
                 //let tile_vram_offset = tile_index * 16;
                 //let tile_data = &self.vram[tile_vram_offset..tile_vram_offset + 16];
@@ -586,115 +1132,870 @@ impl PPU {
         background_tile_map.to_vec()
     }
 
-    fn render_scanline(&mut self) {
-        let line = usize::from(self.ly);
+    fn tile_row(&self, tile_vram_offset: usize, row: usize) -> [u8; 8] {
+        decode_tile_row(
+            self.vram[tile_vram_offset + row * 2],
+            self.vram[tile_vram_offset + row * 2 + 1],
+        )
+    }
 
-        // Background
-        let background_palette = self.bgp.as_palette();
-        let background_tile_map_vram_offset =
-            usize::from(self.lcdc.background_tile_map_display_select()) - 0x8000;
+    /// The position of a background or window tile in the 384 tiles of VRAM.
+    fn tile_number(&self, tile_index: u8) -> usize {
+        match self.lcdc.background_and_window_tile_data_select() {
+            BackgroundTileData::_8000 => usize::from(tile_index),
+            // Tiles are indexed from -128 to 127 relative to 0x9000
+            BackgroundTileData::_8800 => (256 + isize::from(tile_index as i8)) as usize,
+        }
+    }
 
-        // Sprites
-        let obj_size = self.lcdc.obj_size();
+    fn tile_data_vram_offset(&self, tile_index: u8) -> usize {
+        self.tile_number(tile_index) * 16
+    }
 
-        // TODO: Limit number per line
-        let sprites_on_line: Vec<_> = self
-            .oam
-            .chunks(4)
-            .filter(|s| s[0] > 0 && s[0] < 160)
-            .filter(|s| {
-                let sprite_min_y = s[0] as i16 - 16;
-                let sprite_max_y = sprite_min_y + obj_size.get_height();
+    /// Whether any enabled STAT interrupt source is active, the interrupt
+    /// firing on its rising edge.
+    fn stat_line(&self) -> bool {
+        let mode_line = match self.mode {
+            Mode::HBlank => self.stat.hblank_interrupt_enabled(),
+            // The OAM interrupt also fires at the start of VBlank
+            Mode::VBlank => {
+                self.stat.vblank_interrupt_enabled()
+                    || (self.stat.oam_interrupt_enabled() && self.ly == 144 && self.dot == 0)
+            }
+            Mode::OAMRead => self.stat.oam_interrupt_enabled(),
+            Mode::VRAMRead => false,
+        };
 
-                line as i16 >= sprite_min_y && (line as i16) < sprite_max_y
-            })
-            .collect();
+        mode_line || (self.stat.coincidence_interrupt_enabled() && self.stat.coincidence_flag())
+    }
 
-        for x in 0..160 {
-            let color = if self.lcdc.bg_display_enabled() {
-                let tile_map_x = x / 8;
-                let tile_map_y = line / 8;
+    /// Checks one of the 40 entries in OAM during mode 2, keeping the first 10
+    /// sprites on the line whether or not they are horizontally on screen.
+    fn scan_oam_entry(&mut self, index: usize) {
+        if index == 0 {
+            self.line_sprites.clear();
+        }
 
-                let tile_map_offset = tile_map_y * 32 + tile_map_x;
+        let sprite = Sprite::from(&self.oam[index * 4..index * 4 + 4]);
+        let line = i16::from(self.ly);
+        let top = i16::from(sprite.y) - 16;
 
-                let tile_index =
-                    self.vram[background_tile_map_vram_offset + tile_map_offset] as usize;
+        if self.line_sprites.len() < 10
+            && line >= top
+            && line < top + self.lcdc.obj_size().get_height()
+        {
+            self.line_sprites.push(sprite);
+        }
+    }
 
-                if let BackgroundTileData::_8800 =
-                    self.lcdc.background_and_window_tile_data_select()
-                {
-                    unimplemented!();
+    /// Picks up from the start of the current mode when the position within
+    /// the line isn't known, `dot` being how far into the mode it was.
+    fn resume_mode(&mut self, dot: usize) {
+        self.line_sprites.clear();
+
+        match self.mode {
+            Mode::OAMRead => {
+                self.dot = dot.min(79);
+
+                for index in 0..self.dot.div_ceil(2) {
+                    self.scan_oam_entry(index);
+                }
+            }
+            // The pixels drawn so far on the line are drawn again
+            Mode::VRAMRead => {
+                for index in 0..40 {
+                    self.scan_oam_entry(index);
                 }
 
-                let tile_vram_offset = tile_index * 16;
-                let tile_data = &self.vram[tile_vram_offset..tile_vram_offset + 16];
+                self.dot = 80;
+                self.start_pixel_transfer();
+            }
+            Mode::HBlank => self.dot = (252 + dot).min(455),
+            Mode::VBlank => self.dot = dot.min(455),
+        }
+    }
+
+    fn start_pixel_transfer(&mut self) {
+        if self.ly == self.wy {
+            self.window_y_triggered = true;
+        }
+
+        self.fetcher = Fetcher::new();
+        self.background_fifo.clear();
+        self.sprite_fifo.clear();
+        self.fetching_sprite = None;
+        self.lcd_x = 0;
+
+        // The background is scrolled by less than a tile by throwing away the
+        // first SCX % 8 pixels
+        self.discard = self.scx % 8;
+
+        // With WX=166 the window starts on the last pixel and then covers the
+        // whole of the next line
+        if mem::take(&mut self.window_fills_next_line)
+            && self.lcdc.window_display_enabled()
+            && self.window_y_triggered
+        {
+            self.start_window(0);
+        }
+    }
+
+    fn start_window(&mut self, discard: u8) {
+        self.background_fifo.clear();
+        self.fetcher.start_window();
+        self.discard = discard;
+    }
 
-                let tile_x = x % 8;
-                let tile_y = line % 8;
+    /// Runs one dot of mode 3, returning true once the last pixel of the line
+    /// has been drawn.
+    fn tick_pixel_transfer(&mut self) -> bool {
+        // Sprites that are partly off the left of the screen are all fetched
+        // before the first pixel, the one with the lowest X first as the first
+        // sprite fetched covers the pixels it shares with the later ones
+        if self.fetching_sprite.is_none() && self.discard == 0 && self.lcdc.obj_display_enabled() {
+            let x = self.lcd_x;
+
+            if let Some((index, _)) = self
+                .line_sprites
+                .iter()
+                .enumerate()
+                .filter(|(_, sprite)| sprite.x == x + 8 || (x == 0 && sprite.x < 8))
+                .min_by_key(|(_, sprite)| sprite.x)
+            {
+                self.fetching_sprite = Some((self.line_sprites.remove(index), 0));
+            }
+        }
 
-                let low = tile_data[tile_y * 2].bit(7 - tile_x);
-                let high = tile_data[tile_y * 2 + 1].bit(7 - tile_x);
+        // Drawing stops while a sprite is fetched, the background fetcher
+        // first finishes the tile it's on which takes up to another 5 dots
+        if let Some((sprite, dots)) = self.fetching_sprite {
+            if self.fetcher.step != FetcherStep::Push {
+                self.tick_fetcher();
+            }
 
-                let mut palette_index: u8 = 0;
-                palette_index.set_bit(0, low);
-                palette_index.set_bit(1, high);
+            if self.fetcher.step == FetcherStep::Push {
+                if dots == 5 {
+                    self.fetching_sprite = None;
+                    self.push_sprite(&sprite);
+                } else {
+                    self.fetching_sprite = Some((sprite, dots + 1));
+                }
+            }
 
-                background_palette[palette_index as usize]
+            return false;
+        }
+
+        if !self.fetcher.window && self.lcdc.window_display_enabled() && self.window_y_triggered {
+            // WX is offset by 7, values below 7 cut off the left of the window
+            match self.wx {
+                0..=6 if self.lcd_x == 0 => self.start_window(7 - self.wx),
+                7..=166 if self.lcd_x + 7 == self.wx => {
+                    self.window_fills_next_line = self.wx == 166;
+                    self.start_window(0);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(background_index) = self.background_fifo.pop_front() {
+            if self.discard > 0 {
+                self.discard -= 1;
             } else {
-                Color::White
-            };
+                let sprite_pixel = self.sprite_fifo.pop_front();
+                self.draw_pixel(background_index, sprite_pixel);
 
-            let color = if self.lcdc.obj_display_enabled() {
-                // TODO: Priorities
-                let sprite = sprites_on_line
-                    .iter()
-                    .filter(|sprite| {
-                        let sprite_max_x = sprite[1] as i16;
-                        let sprite_min_x = sprite_max_x - 8;
+                self.lcd_x += 1;
 
-                        x as i16 >= sprite_min_x && (x as i16) < sprite_max_x
-                    })
-                    .next();
+                if usize::from(self.lcd_x) == SCREEN_WIDTH {
+                    return true;
+                }
+            }
+        }
 
-                if let Some(sprite) = sprite {
-                    let sprite_y = sprite[0] as i16 - 16;
-                    let sprite_x = sprite[1] as i16 - 8;
+        self.tick_fetcher();
 
-                    let tile_y = (line as i16 - sprite_y) as usize;
-                    let tile_x = (x as i16 - sprite_x) as usize;
+        false
+    }
 
-                    // TODO: 8x16
-                    let tile_index = sprite[2] as usize;
+    /// The row of the background or window being fetched, within the whole
+    /// 256x256 pixel map.
+    fn fetcher_y(&self) -> usize {
+        if self.fetcher.window {
+            usize::from(self.window_line)
+        } else {
+            // The background wraps around in both directions
+            usize::from(self.ly.wrapping_add(self.scy))
+        }
+    }
 
-                    let palette = if !sprite[3].bit(4) {
-                        self.obp0.as_palette()
-                    } else {
-                        self.obp1.as_palette()
-                    };
+    fn tick_fetcher(&mut self) {
+        // Each step takes 2 dots apart from pushing, which waits for the FIFO
+        // to empty
+        if self.fetcher.step != FetcherStep::Push {
+            self.fetcher.dots += 1;
 
-                    let tile_vram_offset = tile_index * 16;
-                    let tile_data = &self.vram[tile_vram_offset..tile_vram_offset + 16];
+            if self.fetcher.dots < 2 {
+                return;
+            }
 
-                    let low = tile_data[tile_y * 2].bit(7 - tile_x);
-                    let high = tile_data[tile_y * 2 + 1].bit(7 - tile_x);
+            self.fetcher.dots = 0;
+        }
 
-                    let mut palette_index: u8 = 0;
-                    palette_index.set_bit(0, low);
-                    palette_index.set_bit(1, high);
+        let y = self.fetcher_y();
 
-                    if palette_index == 0 {
-                        color
-                    } else {
-                        palette[palette_index as usize - 1]
-                    }
+        match self.fetcher.step {
+            FetcherStep::Tile => {
+                let (tile_map, tile_map_x) = if self.fetcher.window {
+                    (
+                        self.lcdc.window_tile_map_display_select(),
+                        usize::from(self.fetcher.tile_x),
+                    )
                 } else {
-                    color
-                }
-            } else {
-                color
+                    (
+                        self.lcdc.background_tile_map_display_select(),
+                        usize::from(self.scx / 8) + usize::from(self.fetcher.tile_x),
+                    )
+                };
+
+                let tile_map_vram_offset = usize::from(tile_map) - 0x8000;
+
+                self.fetcher.tile_index =
+                    self.vram[tile_map_vram_offset + (y / 8) * 32 + tile_map_x % 32];
+                self.fetcher.step = FetcherStep::DataLow;
+            }
+            FetcherStep::DataLow => {
+                let offset = self.tile_data_vram_offset(self.fetcher.tile_index) + (y % 8) * 2;
+
+                self.fetcher.data_low = self.vram[offset];
+                self.fetcher.step = FetcherStep::DataHigh;
+            }
+            FetcherStep::DataHigh => {
+                let offset = self.tile_data_vram_offset(self.fetcher.tile_index) + (y % 8) * 2;
+
+                self.fetcher.data_high = self.vram[offset + 1];
+                self.fetcher.step = FetcherStep::Push;
+                self.push_background();
+            }
+            FetcherStep::Push => self.push_background(),
+        }
+    }
+
+    fn push_background(&mut self) {
+        if !self.background_fifo.is_empty() {
+            return;
+        }
+
+        // The first tile of the line is fetched twice, the first fetch is thrown away
+        if self.fetcher.first_fetch {
+            self.fetcher.first_fetch = false;
+        } else {
+            self.background_fifo.extend(&decode_tile_row(
+                self.fetcher.data_low,
+                self.fetcher.data_high,
+            ));
+            self.fetcher.tile_x = self.fetcher.tile_x.wrapping_add(1);
+        }
+
+        self.fetcher.step = FetcherStep::Tile;
+    }
+
+    /// The row of a sprite on the current line, leftmost pixel first.
+    fn sprite_row(&self, sprite: &Sprite) -> [u8; 8] {
+        let height = self.lcdc.obj_size().get_height() as usize;
+
+        // LCDC can change the height after the sprite was found on the line
+        let mut tile_y = (i16::from(self.ly) - (i16::from(sprite.y) - 16)) as usize & (height - 1);
+
+        if sprite.attributes.y_flip() {
+            tile_y = height - 1 - tile_y;
+        }
+
+        // 8x16 sprites ignore the lowest bit of the tile index
+        let tile_index = match self.lcdc.obj_size() {
+            OBJSize::_8x8 => usize::from(sprite.tile_index),
+            OBJSize::_8x16 => usize::from(sprite.tile_index & 0xFE),
+        };
+
+        let mut row = self.tile_row(tile_index * 16, tile_y);
+
+        if sprite.attributes.x_flip() {
+            row.reverse();
+        }
+
+        row
+    }
+
+    /// Mixes a sprite into the sprite FIFO. Sprites fetched earlier are drawn
+    /// on top, so only their transparent pixels are replaced.
+    fn push_sprite(&mut self, sprite: &Sprite) {
+        let row = self.sprite_row(sprite);
+        let hidden = usize::from(self.lcd_x + 8 - sprite.x);
+
+        for (x, &color) in row.iter().enumerate().skip(hidden) {
+            let pixel = SpritePixel {
+                color,
+                palette: sprite.attributes.palette(),
+                bg_priority: sprite.attributes.bg_priority(),
             };
 
-            self.hal.borrow_mut().put_pixel(line, x, color);
+            match self.sprite_fifo.get_mut(x - hidden) {
+                Some(existing) if existing.color == 0 => *existing = pixel,
+                Some(_) => {}
+                None => self.sprite_fifo.push_back(pixel),
+            }
+        }
+    }
+
+    fn draw_pixel(&mut self, background_index: u8, sprite_pixel: Option<SpritePixel>) {
+        // Background and window, which are both disabled by LCDC bit 0 on the
+        // DMG, in which case every sprite pixel is drawn over them
+        let (background_index, color) = if self.lcdc.bg_display_enabled() {
+            (
+                background_index,
+                self.bgp.as_palette()[usize::from(background_index)],
+            )
+        } else {
+            (0, Color::White)
+        };
+
+        let color = match sprite_pixel {
+            Some(pixel) if pixel.color == 0 || !self.lcdc.obj_display_enabled() => color,
+            Some(pixel) if pixel.bg_priority && background_index != 0 => color,
+            Some(pixel) => {
+                let palette = if !pixel.palette {
+                    self.obp0.as_palette()
+                } else {
+                    self.obp1.as_palette()
+                };
+
+                palette[usize::from(pixel.color) - 1]
+            }
+            None => color,
+        };
+
+        self.frame_buffer
+            .set_pixel(usize::from(self.lcd_x), usize::from(self.ly), color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::NullHAL;
+
+    fn ppu() -> PPU {
+        let mut ppu = PPU::new(Rc::new(RefCell::new(NullHAL)));
+        ppu.set_bgp(0b11_10_01_00);
+
+        // Tile 1 is solid black
+        for byte in &mut ppu.vram_mut()[0x10..0x20] {
+            *byte = 0xFF;
+        }
+
+        ppu
+    }
+
+    /// Runs the PPU from the start of line `ly` to the end of mode 3,
+    /// returning the number of dots mode 3 took.
+    fn run_line(ppu: &mut PPU, ly: u8) -> usize {
+        ppu.ly = ly;
+        ppu.dot = 0;
+        ppu.mode = Mode::OAMRead;
+
+        while ppu.mode == Mode::OAMRead {
+            ppu.tick();
+        }
+
+        let mut dots = 0;
+
+        while ppu.mode == Mode::VRAMRead {
+            ppu.tick();
+            dots += 1;
         }
+
+        dots
+    }
+
+    fn render_line(ppu: &mut PPU, ly: u8) -> Vec<Color> {
+        run_line(ppu, ly);
+
+        (0..160)
+            .map(|x| ppu.frame_buffer().pixel(x, usize::from(ly)))
+            .collect()
+    }
+
+    #[test]
+    fn it_should_scroll_the_background_by_less_than_a_tile() {
+        let mut ppu = ppu();
+        ppu.vram_mut()[0x1800 + 1] = 0x01;
+        ppu.set_scx(3);
+
+        let line = render_line(&mut ppu, 0);
+
+        assert_eq!(Color::White, line[4]);
+        assert!(line[5..13].iter().all(|&color| color == Color::Black));
+        assert_eq!(Color::White, line[13]);
+    }
+
+    #[test]
+    fn it_should_wrap_the_background_around() {
+        let mut ppu = ppu();
+        // The top left tile is drawn at the bottom right when scrolled
+        ppu.vram_mut()[0x1800] = 0x01;
+        ppu.set_scx(104);
+        ppu.set_scy(240);
+
+        let line = render_line(&mut ppu, 16);
+
+        assert!(line[152..160].iter().all(|&color| color == Color::Black));
+        assert_eq!(Color::White, line[151]);
+    }
+
+    fn window_ppu() -> PPU {
+        let mut ppu = ppu();
+        // Window enabled using the 0x9C00 tile map
+        ppu.set_lcdc(0xF1);
+
+        // The first row of the window is solid, the second row is white
+        // apart from its first tile
+        for tile in &mut ppu.vram_mut()[0x1C00..0x1C20] {
+            *tile = 0x01;
+        }
+        ppu.vram_mut()[0x1C20] = 0x01;
+
+        ppu
+    }
+
+    #[test]
+    fn it_should_offset_the_window_by_7() {
+        let mut ppu = window_ppu();
+        ppu.set_wx(7 + 80);
+
+        let line = render_line(&mut ppu, 0);
+
+        assert_eq!(Color::White, line[79]);
+        assert!(line[80..].iter().all(|&color| color == Color::Black));
+    }
+
+    #[test]
+    fn it_should_cut_off_the_window_when_wx_is_below_7() {
+        let mut ppu = window_ppu();
+        ppu.set_wx(0);
+
+        // Lines 8-15 of the window only have their first tile set
+        ppu.window_y_triggered = true;
+        ppu.window_line = 8;
+        let line = render_line(&mut ppu, 8);
+
+        assert_eq!(Color::Black, line[0]);
+        assert_eq!(Color::White, line[1]);
+    }
+
+    #[test]
+    fn it_should_only_advance_the_window_line_when_drawn() {
+        let mut ppu = window_ppu();
+        ppu.set_wy(10);
+        ppu.set_wx(7);
+
+        for ly in 0..20 {
+            if ly == 15 {
+                // Hiding the window for a line pauses its line counter
+                ppu.set_wx(200);
+            }
+
+            render_line(&mut ppu, ly);
+
+            ppu.set_wx(7);
+        }
+
+        assert_eq!(9, ppu.window_line);
+    }
+
+    #[test]
+    fn it_should_fill_the_next_line_when_wx_is_166() {
+        let mut ppu = window_ppu();
+        ppu.set_wx(166);
+
+        let line = render_line(&mut ppu, 0);
+        assert_eq!(Color::White, line[158]);
+        assert_eq!(Color::Black, line[159]);
+
+        ppu.set_wx(200);
+        let line = render_line(&mut ppu, 1);
+        assert!(line.iter().all(|&color| color == Color::Black));
+    }
+
+    #[test]
+    fn it_should_use_signed_tile_indices_relative_to_0x9000() {
+        let mut ppu = ppu();
+        // Background using 0x8800 tile data
+        ppu.set_lcdc(0x81);
+
+        // Tile 0 at 0x9000 is solid black, tile -1 at 0x8FF0 is light grey
+        for byte in &mut ppu.vram_mut()[0x1000..0x1010] {
+            *byte = 0xFF;
+        }
+        for byte in ppu.vram_mut()[0x0FF0..0x1000].iter_mut().step_by(2) {
+            *byte = 0xFF;
+        }
+        ppu.vram_mut()[0x1801] = 0xFF;
+
+        let line = render_line(&mut ppu, 0);
+
+        assert_eq!(Color::Black, line[0]);
+        assert_eq!(Color::LightGrey, line[8]);
+
+        let background_tile_map = ppu.background_tile_map();
+        assert_eq!(Color::Black, background_tile_map[0]);
+        assert_eq!(Color::LightGrey, background_tile_map[8]);
+    }
+
+    fn sprite_ppu() -> PPU {
+        let mut ppu = ppu();
+        ppu.set_lcdc(0x93);
+        ppu.set_obp0(0b11_10_01_00);
+
+        // Tile 2 has its left half in colour 1, tile 3 is solid colour 2
+        for row in ppu.vram_mut()[0x20..0x30].chunks_mut(2) {
+            row[0] = 0xF0;
+        }
+        for row in ppu.vram_mut()[0x30..0x40].chunks_mut(2) {
+            row[1] = 0xFF;
+        }
+
+        ppu
+    }
+
+    fn set_sprite(ppu: &mut PPU, index: usize, sprite: [u8; 4]) {
+        ppu.oam_mut()[index * 4..index * 4 + 4].copy_from_slice(&sprite);
+    }
+
+    #[test]
+    fn it_should_only_draw_10_sprites_per_line() {
+        let mut ppu = sprite_ppu();
+
+        // An off screen sprite still counts towards the limit
+        set_sprite(&mut ppu, 0, [16, 0, 1, 0]);
+
+        for index in 1..11 {
+            set_sprite(&mut ppu, index, [16, index as u8 * 8 + 8, 1, 0]);
+        }
+
+        let line = render_line(&mut ppu, 0);
+
+        assert_eq!(Color::Black, line[72]);
+        assert_eq!(Color::White, line[80]);
+    }
+
+    #[test]
+    fn it_should_draw_sprites_with_lower_x_then_lower_oam_index_on_top() {
+        let mut ppu = sprite_ppu();
+        set_sprite(&mut ppu, 0, [16, 12, 3, 0]);
+        set_sprite(&mut ppu, 1, [16, 8, 2, 0]);
+        set_sprite(&mut ppu, 2, [16, 8, 1, 0]);
+
+        let line = render_line(&mut ppu, 0);
+
+        // Transparent pixels show the next sprite down
+        assert_eq!(Color::LightGrey, line[0]);
+        assert_eq!(Color::Black, line[4]);
+        assert_eq!(Color::DarkGrey, line[8]);
+    }
+
+    #[test]
+    fn it_should_draw_sprites_partly_off_the_left_with_lower_x_on_top() {
+        let mut ppu = sprite_ppu();
+        set_sprite(&mut ppu, 0, [16, 6, 3, 0]);
+        // Flipped so the half that's on screen is in colour 1
+        set_sprite(&mut ppu, 1, [16, 3, 2, 0x20]);
+
+        let line = render_line(&mut ppu, 0);
+
+        assert_eq!(Color::LightGrey, line[0]);
+        assert_eq!(Color::LightGrey, line[2]);
+        assert_eq!(Color::DarkGrey, line[3]);
+        assert_eq!(Color::DarkGrey, line[5]);
+        assert_eq!(Color::White, line[6]);
+    }
+
+    #[test]
+    fn it_should_flip_sprites() {
+        let mut ppu = sprite_ppu();
+        set_sprite(&mut ppu, 0, [16, 8, 2, 0x20]);
+
+        let line = render_line(&mut ppu, 0);
+
+        assert_eq!(Color::White, line[0]);
+        assert_eq!(Color::LightGrey, line[4]);
+
+        // Only the last row of tile 1 is set, which is the first row when flipped
+        set_sprite(&mut ppu, 0, [16, 8, 4, 0x40]);
+        ppu.vram_mut()[0x4E] = 0xFF;
+
+        assert_eq!(Color::LightGrey, render_line(&mut ppu, 0)[0]);
+    }
+
+    #[test]
+    fn it_should_ignore_the_low_bit_of_the_tile_index_for_8x16_sprites() {
+        let mut ppu = sprite_ppu();
+        ppu.set_lcdc(0x97);
+        set_sprite(&mut ppu, 0, [16, 8, 3, 0]);
+
+        assert_eq!(Color::LightGrey, render_line(&mut ppu, 0)[0]);
+        assert_eq!(Color::DarkGrey, render_line(&mut ppu, 8)[0]);
+    }
+
+    #[test]
+    fn it_should_draw_the_background_over_sprites_with_priority() {
+        let mut ppu = sprite_ppu();
+        // The left half of the first background tile is colour 1, the rest is colour 0
+        ppu.set_bgp(0b00_00_01_00);
+        ppu.vram_mut()[0x1800] = 0x02;
+        set_sprite(&mut ppu, 0, [16, 8, 3, 0x80]);
+        set_sprite(&mut ppu, 1, [16, 16, 3, 0x80]);
+
+        let line = render_line(&mut ppu, 0);
+
+        assert_eq!(Color::LightGrey, line[0]);
+        assert_eq!(Color::DarkGrey, line[4]);
+        assert_eq!(Color::DarkGrey, line[8]);
+    }
+
+    #[test]
+    fn it_should_take_172_dots_for_mode_3_plus_the_fine_scroll() {
+        let mut ppu = ppu();
+        assert_eq!(172, run_line(&mut ppu, 0));
+
+        ppu.set_scx(3);
+        assert_eq!(175, run_line(&mut ppu, 0));
+
+        // Only the fine scroll within a tile matters
+        ppu.set_scx(8 + 3);
+        assert_eq!(175, run_line(&mut ppu, 0));
+    }
+
+    #[test]
+    fn it_should_take_6_more_dots_for_the_window() {
+        let mut ppu = window_ppu();
+        ppu.set_wx(7 + 80);
+
+        assert_eq!(178, run_line(&mut ppu, 0));
+    }
+
+    #[test]
+    fn it_should_take_6_to_11_more_dots_for_each_sprite() {
+        let mut ppu = sprite_ppu();
+
+        // The background fetcher finishes its current tile before the sprite
+        // is fetched, which takes longer the closer it is to the start of a tile
+        set_sprite(&mut ppu, 0, [16, 8 + 16, 1, 0]);
+        assert_eq!(172 + 11, run_line(&mut ppu, 0));
+
+        set_sprite(&mut ppu, 0, [16, 8 + 16 + 3, 1, 0]);
+        assert_eq!(172 + 8, run_line(&mut ppu, 0));
+
+        set_sprite(&mut ppu, 0, [16, 8 + 16 + 7, 1, 0]);
+        assert_eq!(172 + 6, run_line(&mut ppu, 0));
+
+        // Another sprite on the same tile doesn't wait for the fetcher again
+        set_sprite(&mut ppu, 1, [16, 8 + 16 + 7, 1, 0]);
+        assert_eq!(172 + 12, run_line(&mut ppu, 0));
+
+        // Sprites are still fetched when they're behind the background
+        set_sprite(&mut ppu, 1, [0, 0, 0, 0]);
+        set_sprite(&mut ppu, 0, [16, 8 + 16 + 7, 1, 0x80]);
+        assert_eq!(172 + 6, run_line(&mut ppu, 0));
+
+        // With OBJ disabled no sprites are fetched, so mode 3 is the minimum
+        ppu.set_lcdc(0x91);
+        assert_eq!(172, run_line(&mut ppu, 0));
+    }
+
+    #[test]
+    fn it_should_apply_register_writes_mid_scanline() {
+        let mut ppu = ppu();
+        // The right half of the background tile map is solid black
+        for tile in &mut ppu.vram_mut()[0x1810..0x1820] {
+            *tile = 0x01;
+        }
+        ppu.set_bgp(0xFF);
+
+        ppu.ly = 0;
+        ppu.dot = 0;
+        ppu.mode = Mode::OAMRead;
+
+        while ppu.mode != Mode::VRAMRead || ppu.lcd_x < 40 {
+            ppu.tick();
+        }
+
+        // Palettes apply to the next pixel drawn
+        ppu.set_bgp(0b11_10_01_00);
+
+        while ppu.lcd_x < 80 {
+            ppu.tick();
+        }
+
+        // Scrolling applies to the next tile fetched
+        ppu.set_scx(64);
+
+        while ppu.mode == Mode::VRAMRead {
+            ppu.tick();
+        }
+
+        let line: Vec<Color> = (0..160).map(|x| ppu.frame_buffer().pixel(x, 0)).collect();
+
+        assert_eq!(Color::Black, line[39]);
+        assert_eq!(Color::White, line[40]);
+        assert_eq!(Color::White, line[80]);
+        assert!(line[96..].iter().all(|&color| color == Color::Black));
+    }
+
+    #[test]
+    fn it_should_resume_mode_3_from_a_save_state() {
+        let mut ppu = sprite_ppu();
+        set_sprite(&mut ppu, 0, [16, 40, 3, 0]);
+
+        ppu.ly = 0;
+        ppu.dot = 0;
+        ppu.mode = Mode::OAMRead;
+
+        while ppu.fetching_sprite.is_none() {
+            ppu.tick();
+        }
+
+        let mut state = StateWriter::new();
+        ppu.save_state(&mut state);
+        let data = state.into_bytes();
+
+        let mut loaded = PPU::new(Rc::new(RefCell::new(NullHAL)));
+        loaded
+            .load_state(&mut StateReader::new(&data).unwrap())
+            .unwrap();
+
+        for _ in 0..456 {
+            ppu.tick();
+            loaded.tick();
+        }
+
+        assert_eq!(ppu.dot, loaded.dot);
+        assert!(ppu.frame_buffer().pixels() == loaded.frame_buffer().pixels());
+        assert_eq!(Color::DarkGrey, loaded.frame_buffer().pixel(32, 0));
+    }
+
+    fn start_line(ppu: &mut PPU, ly: u8, mode: Mode) {
+        ppu.ly = ly;
+        ppu.dot = 0;
+        ppu.mode = mode;
+    }
+
+    #[test]
+    fn it_should_only_interrupt_when_the_stat_line_rises() {
+        let mut ppu = ppu();
+        start_line(&mut ppu, 0, Mode::OAMRead);
+
+        // LY=LYC holds the line high through the HBlank of line 0
+        ppu.set_lyc(0);
+        ppu.set_stat(0x48);
+
+        let interrupts: Vec<(u8, Mode)> = (0..456 * 2)
+            .filter_map(|_| match ppu.tick() {
+                (_, true) => Some((ppu.ly(), ppu.stat().mode_flag())),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(vec![(0, Mode::OAMRead), (1, Mode::HBlank)], interrupts);
+    }
+
+    #[test]
+    fn it_should_read_ly_as_0_for_most_of_line_153() {
+        let mut ppu = ppu();
+        start_line(&mut ppu, 153, Mode::VBlank);
+        ppu.set_lyc(0);
+
+        ppu.tick();
+        assert_eq!(153, ppu.ly());
+        assert!(!ppu.stat().coincidence_flag());
+
+        for _ in 0..3 {
+            ppu.tick();
+        }
+
+        assert_eq!(0, ppu.ly());
+        assert!(ppu.stat().coincidence_flag());
+    }
+
+    #[test]
+    fn it_should_interrupt_when_stat_is_written_in_hblank_on_the_dmg() {
+        let mut ppu = ppu();
+        start_line(&mut ppu, 0, Mode::HBlank);
+        ppu.set_lyc(1);
+
+        assert_eq!(Mode::HBlank, ppu.stat().mode_flag());
+        assert!(ppu.set_stat(0x00));
+
+        let mut ppu = PPU::post_boot(Model::CGB, Rc::new(RefCell::new(NullHAL)));
+        start_line(&mut ppu, 0, Mode::HBlank);
+        ppu.set_lyc(1);
+
+        assert!(!ppu.set_stat(0x00));
+    }
+
+    fn run_frame(ppu: &mut PPU) {
+        let frames = ppu.frames();
+
+        while ppu.frames() == frames {
+            ppu.tick();
+        }
+    }
+
+    #[test]
+    fn it_should_keep_frame_timing_while_the_lcd_is_off() {
+        let mut ppu = ppu();
+        ppu.vram_mut()[0x1800] = 0x01;
+        start_line(&mut ppu, 50, Mode::HBlank);
+
+        ppu.set_lcdc(0x11);
+
+        assert_eq!(0, ppu.ly());
+        assert_eq!(Mode::HBlank, ppu.stat().mode_flag());
+
+        // The frame would have ended 94 lines later
+        for _ in 0..94 * 456 {
+            ppu.tick();
+        }
+
+        assert_eq!(1, ppu.frames());
+
+        for _ in 0..154 * 456 - 1 {
+            ppu.tick();
+        }
+
+        assert_eq!(1, ppu.frames());
+        ppu.tick();
+        assert_eq!(2, ppu.frames());
+    }
+
+    #[test]
+    fn it_should_blank_the_first_frame_after_the_lcd_is_turned_on() {
+        let mut ppu = ppu();
+        ppu.vram_mut()[0x1800] = 0x01;
+        ppu.set_lcdc(0x11);
+        ppu.set_lcdc(0x91);
+
+        // The first line skips looking for sprites in mode 2
+        for _ in 0..79 {
+            ppu.tick();
+        }
+
+        assert_eq!(Mode::HBlank, ppu.stat().mode_flag());
+        ppu.tick();
+        assert_eq!(Mode::VRAMRead, ppu.stat().mode_flag());
+
+        run_frame(&mut ppu);
+        assert_eq!(Color::White, ppu.frame_buffer().pixel(0, 0));
+
+        run_frame(&mut ppu);
+        assert_eq!(Color::Black, ppu.frame_buffer().pixel(0, 0));
     }
 }
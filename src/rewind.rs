@@ -0,0 +1,288 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::Gameboy;
+
+struct Snapshot {
+    frame: u64,
+    data: Vec<u8>,
+}
+
+/// Records save states as frames are run so the machine can be stepped
+/// backwards. Only the newest snapshot is kept in full, each older one is
+/// stored as the XOR against the snapshot after it with the runs of zeroes
+/// (the bytes that didn't change) compressed away. The joypad input of each
+/// frame is recorded too, so the frames between snapshots play out the same
+/// way when they're run again.
+pub struct Rewind {
+    interval: u64,
+    max_bytes: usize,
+    frame: u64,
+    newest: Option<Snapshot>,
+    deltas: VecDeque<Snapshot>,
+    bytes: usize,
+    // The input of each frame after `inputs_start`
+    inputs: VecDeque<u8>,
+    inputs_start: u64,
+}
+
+impl Rewind {
+    /// Takes a snapshot every `interval` frames, dropping the oldest snapshots
+    /// when they take up more than `max_bytes`.
+    pub fn new(interval: u64, max_bytes: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            max_bytes,
+            frame: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            bytes: 0,
+            inputs: VecDeque::new(),
+            inputs_start: 0,
+        }
+    }
+
+    /// The number of frames that have been recorded, less any stepped back over.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// The number of snapshots that are held.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// The memory used by the snapshots, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.bytes = 0;
+        self.inputs.clear();
+        self.inputs_start = self.frame;
+    }
+
+    /// Runs a frame and records it.
+    pub fn step_frame(&mut self, gameboy: &mut Gameboy) {
+        gameboy.step_frame();
+        self.record(gameboy);
+    }
+
+    /// Records a frame that has just finished, this should be called after
+    /// each call to `Gameboy::step_frame`.
+    pub fn record(&mut self, gameboy: &Gameboy) {
+        self.inputs.push_back(gameboy.joypad_input());
+        self.frame += 1;
+
+        if self.frame.is_multiple_of(self.interval) {
+            self.push(Snapshot {
+                frame: self.frame,
+                data: gameboy.save_state(),
+            });
+        }
+    }
+
+    /// Goes back one frame by loading the nearest snapshot at or before it
+    /// and running forward from there with the input that was recorded.
+    /// Returns false if the frame is older than the oldest snapshot.
+    pub fn step_back(&mut self, gameboy: &mut Gameboy) -> bool {
+        let target = match self.frame.checked_sub(1) {
+            Some(target) => target,
+            None => return false,
+        };
+
+        let oldest = self.deltas.front().or(self.newest.as_ref());
+
+        if oldest
+            .map(|snapshot| snapshot.frame > target)
+            .unwrap_or(true)
+        {
+            return false;
+        }
+
+        while self.newest.as_ref().unwrap().frame > target {
+            self.pop();
+        }
+
+        let snapshot = self.newest.as_ref().unwrap();
+
+        if gameboy.load_state(&snapshot.data).is_err() {
+            self.clear();
+            return false;
+        }
+
+        for frame in snapshot.frame..target {
+            let input = self.inputs[(frame - self.inputs_start) as usize];
+
+            gameboy.set_replayed_input(Some(input));
+            gameboy.step_frame();
+        }
+
+        gameboy.set_replayed_input(None);
+
+        self.frame = target;
+        self.inputs.truncate((target - self.inputs_start) as usize);
+
+        true
+    }
+
+    fn push(&mut self, snapshot: Snapshot) {
+        self.bytes += snapshot.data.len();
+
+        if let Some(previous) = self.newest.take() {
+            let delta = Snapshot {
+                frame: previous.frame,
+                data: encode_delta(&previous.data, &snapshot.data),
+            };
+
+            self.bytes = self.bytes - previous.data.len() + delta.data.len();
+            self.deltas.push_back(delta);
+        }
+
+        self.newest = Some(snapshot);
+
+        // The oldest delta can go without breaking the chain back from the newest
+        while self.bytes > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.bytes -= oldest.data.len(),
+                None => break,
+            }
+        }
+
+        // Frames before the oldest snapshot are never run again
+        let oldest = self.deltas.front().or(self.newest.as_ref()).unwrap();
+
+        while self.inputs_start < oldest.frame {
+            self.inputs.pop_front();
+            self.inputs_start += 1;
+        }
+    }
+
+    fn pop(&mut self) {
+        let newest = self.newest.take().unwrap();
+        self.bytes -= newest.data.len();
+
+        if let Some(delta) = self.deltas.pop_back() {
+            let snapshot = Snapshot {
+                frame: delta.frame,
+                data: decode_delta(&delta.data, &newest.data),
+            };
+
+            self.bytes = self.bytes - delta.data.len() + snapshot.data.len();
+            self.newest = Some(snapshot);
+        }
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], offset: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*offset];
+        *offset += 1;
+
+        value |= usize::from(byte & 0x7F) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
+
+/// Encodes `older` as the XOR against `newer`, as the length of `older`
+/// followed by pairs of the number of unchanged bytes and a run of changed bytes.
+fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = older
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ newer.get(i).unwrap_or(&0))
+        .collect();
+
+    let mut data = Vec::new();
+    write_varint(&mut data, older.len());
+
+    let mut i = 0;
+
+    while i < xor.len() {
+        let unchanged = xor[i..].iter().take_while(|&&byte| byte == 0).count();
+        i += unchanged;
+
+        let changed = xor[i..].iter().take_while(|&&byte| byte != 0).count();
+
+        write_varint(&mut data, unchanged);
+        write_varint(&mut data, changed);
+        data.extend_from_slice(&xor[i..i + changed]);
+
+        i += changed;
+    }
+
+    data
+}
+
+fn decode_delta(delta: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut offset = 0;
+    let len = read_varint(delta, &mut offset);
+
+    let mut older: Vec<u8> = (0..len).map(|i| *newer.get(i).unwrap_or(&0)).collect();
+    let mut i = 0;
+
+    while offset < delta.len() {
+        i += read_varint(delta, &mut offset);
+        let changed = read_varint(delta, &mut offset);
+
+        for byte in &mut older[i..i + changed] {
+            *byte ^= delta[offset];
+            offset += 1;
+        }
+
+        i += changed;
+    }
+
+    older
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_round_trip_deltas() {
+        let newer = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        for older in &[
+            vec![1, 2, 3, 4, 5, 6, 7, 8],
+            vec![1, 9, 9, 4, 5, 6, 7, 0],
+            vec![0, 2, 3, 4, 5, 6],
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+        ] {
+            assert_eq!(*older, decode_delta(&encode_delta(older, &newer), &newer));
+        }
+    }
+
+    #[test]
+    fn it_should_compress_unchanged_bytes() {
+        let newer = vec![0x55; 8192];
+        let mut older = newer.clone();
+        older[4000] = 0xAA;
+
+        assert!(encode_delta(&older, &newer).len() < 16);
+    }
+}
@@ -1,11 +1,121 @@
+mod info;
+mod licensee;
+
 use alloc::vec::Vec;
+use std::convert::TryFrom;
 use std::fmt;
 
 use crate::cartridge::Cartridge;
 
+use info::decode_cartridge_type;
+pub use info::{CGBMode, CartridgeFeatures, Destination, RomInfo};
+
+// The cartridge header ends at 0x014F, anything shorter can't be a ROM
+const HEADER_END: usize = 0x150;
+
+// The DMG boot ROM is mapped over 0x0000-0x00FF
+pub const BOOT_ROM_SIZE: usize = 0x100;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RomError {
+    Truncated { len: usize },
+    UnsupportedCartridgeType(u8),
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    SizeMismatch { expected: usize, actual: usize },
+    InvalidBootRomSize(usize),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            RomError::Truncated { len } => write!(
+                f,
+                "file is {} bytes, too short to contain a cartridge header",
+                len
+            ),
+            RomError::UnsupportedCartridgeType(value) => {
+                write!(f, "unsupported cartridge type {:#04X}", value)
+            }
+            RomError::InvalidRomSize(value) => write!(f, "invalid ROM size {:#04X}", value),
+            RomError::InvalidRamSize(value) => write!(f, "invalid RAM size {:#04X}", value),
+            RomError::SizeMismatch { expected, actual } => write!(
+                f,
+                "header declares {} bytes of ROM but the file is {} bytes",
+                expected, actual
+            ),
+            RomError::InvalidBootRomSize(len) => {
+                write!(f, "boot ROM is {} bytes, expected {}", len, BOOT_ROM_SIZE)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+// The bitmap at 0x0104-0x0133 that the boot ROM compares before starting a game
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+pub struct HeaderReport {
+    pub logo_valid: bool,
+    pub header_checksum: u8,
+    pub computed_header_checksum: u8,
+    pub global_checksum: u16,
+    pub computed_global_checksum: u16,
+}
+
+impl HeaderReport {
+    pub fn header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    pub fn global_checksum_valid(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    /// The boot ROM only checks the logo and the header checksum, so a bad global
+    /// checksum does not stop a game from running on real hardware.
+    pub fn bootable(&self) -> bool {
+        self.logo_valid && self.header_checksum_valid()
+    }
+
+    pub fn valid(&self) -> bool {
+        self.bootable() && self.global_checksum_valid()
+    }
+}
+
+impl fmt::Display for HeaderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = |valid| if valid { "OK" } else { "BAD" };
+
+        writeln!(f, "Logo: {}", status(self.logo_valid))?;
+        writeln!(
+            f,
+            "Header checksum: {:#04X} (computed {:#04X}) {}",
+            self.header_checksum,
+            self.computed_header_checksum,
+            status(self.header_checksum_valid())
+        )?;
+        write!(
+            f,
+            "Global checksum: {:#06X} (computed {:#06X}) {}",
+            self.global_checksum,
+            self.computed_global_checksum,
+            status(self.global_checksum_valid())
+        )
+    }
+}
+
 pub enum CartridgeType {
     ROMOnly,
     MBC1,
+    MBC2,
+    MBC3,
+    MBC5,
 }
 
 impl fmt::Display for CartridgeType {
@@ -13,6 +123,9 @@ impl fmt::Display for CartridgeType {
         match &self {
             CartridgeType::ROMOnly => write!(f, "ROM ONLY"),
             CartridgeType::MBC1 => write!(f, "MBC1"),
+            CartridgeType::MBC2 => write!(f, "MBC2"),
+            CartridgeType::MBC3 => write!(f, "MBC3"),
+            CartridgeType::MBC5 => write!(f, "MBC5"),
         }
     }
 }
@@ -29,77 +142,301 @@ impl ROM {
     /*pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, String> {
         std::fs::read(path).map_err(|e| e.to_string()).map(ROM)
     }*/
-    pub fn title(&self) -> String {
-        self.0[0x134..0x144]
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, RomError> {
+        let rom = ROM(bytes);
+        rom.validate()?;
+
+        Ok(rom)
+    }
+
+    pub fn validate(&self) -> Result<(), RomError> {
+        if self.0.len() < HEADER_END {
+            return Err(RomError::Truncated { len: self.0.len() });
+        }
+
+        self.cartridge_type()?;
+        self.ram_size()?;
+
+        let expected = self.rom_size()?;
+
+        if expected != self.0.len() {
+            return Err(RomError::SizeMismatch {
+                expected,
+                actual: self.0.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn header_byte(&self, addr: usize) -> Result<u8, RomError> {
+        self.0
+            .get(addr)
+            .copied()
+            .ok_or(RomError::Truncated { len: self.0.len() })
+    }
+
+    fn header_string(&self, start: usize, end: usize) -> String {
+        self.0
+            .get(start..end.min(self.0.len()))
+            .unwrap_or_default()
             .iter()
-            .take_while(|&&c| c != 0)
             .map(|&c| c as char)
             .collect()
     }
 
+    pub fn title(&self) -> String {
+        self.header_string(0x134, 0x144)
+            .chars()
+            .take_while(|&c| c != '\0')
+            .collect()
+    }
+
     pub fn manufacturer_code(&self) -> String {
-        self.0[0x13F..0x144].iter().map(|&c| c as char).collect()
+        self.header_string(0x13F, 0x144)
     }
 
     pub fn cgb_flag(&self) -> u8 {
-        self.0[0x143]
+        self.header_byte(0x143).unwrap_or(0)
     }
 
     pub fn new_licensee_code(&self) -> String {
-        self.0[0x144..0x146].iter().map(|&c| c as char).collect()
+        self.header_string(0x144, 0x146)
     }
 
     pub fn sgb_flag(&self) -> u8 {
-        self.0[0x146]
+        self.header_byte(0x146).unwrap_or(0)
     }
 
-    pub fn cartridge_type(&self) -> CartridgeType {
-        match self.0[0x147] {
-            0x00 => CartridgeType::ROMOnly,
-            0x01..=0x03 => CartridgeType::MBC1,
-            value => unimplemented!("{:#04X}", value),
+    pub fn cartridge_type(&self) -> Result<CartridgeType, RomError> {
+        match self.header_byte(0x147)? {
+            0x00 => Ok(CartridgeType::ROMOnly),
+            0x01..=0x03 => Ok(CartridgeType::MBC1),
+            0x05..=0x06 => Ok(CartridgeType::MBC2),
+            0x0F..=0x13 => Ok(CartridgeType::MBC3),
+            0x19..=0x1E => Ok(CartridgeType::MBC5),
+            value => Err(RomError::UnsupportedCartridgeType(value)),
         }
     }
 
-    pub fn rom_size(&self) -> usize {
-        32768 << self.0[0x148]
+    pub fn cartridge_type_code(&self) -> u8 {
+        self.header_byte(0x147).unwrap_or(0)
+    }
+
+    pub fn features(&self) -> CartridgeFeatures {
+        decode_cartridge_type(self.cartridge_type_code())
+            .map(|(_, features)| features)
+            .unwrap_or_default()
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.features().battery
     }
 
-    pub fn ram_size(&self) -> usize {
-        match self.0[0x149] {
-            0x00 => 0,
-            0x01 => 2048,
-            0x02 => 8192,
-            0x03 => 32768,
-            _ => unreachable!(),
+    pub fn has_timer(&self) -> bool {
+        self.features().timer
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        self.features().rumble
+    }
+
+    pub fn rom_size(&self) -> Result<usize, RomError> {
+        match self.header_byte(0x148)? {
+            value @ 0x00..=0x08 => Ok(32768 << value),
+            value => Err(RomError::InvalidRomSize(value)),
+        }
+    }
+
+    pub fn ram_size(&self) -> Result<usize, RomError> {
+        match self.header_byte(0x149)? {
+            0x00 => Ok(0),
+            0x01 => Ok(2048),
+            0x02 => Ok(8192),
+            0x03 => Ok(32768),
+            0x04 => Ok(131072),
+            0x05 => Ok(65536),
+            value => Err(RomError::InvalidRamSize(value)),
         }
     }
 
     pub fn destination_code(&self) -> u8 {
-        self.0[0x14A]
+        self.header_byte(0x14A).unwrap_or(0)
     }
 
     pub fn old_licensee_code(&self) -> u8 {
-        self.0[0x14B]
+        self.header_byte(0x14B).unwrap_or(0)
     }
 
     pub fn mask_rom_version(&self) -> u8 {
-        self.0[0x14C]
+        self.header_byte(0x14C).unwrap_or(0)
+    }
+
+    pub fn header_checksum(&self) -> u8 {
+        self.header_byte(0x14D).unwrap_or(0)
+    }
+
+    pub fn compute_header_checksum(&self) -> u8 {
+        (0x134..=0x14C).fold(0u8, |checksum, addr| {
+            checksum
+                .wrapping_sub(self.header_byte(addr).unwrap_or(0))
+                .wrapping_sub(1)
+        })
+    }
+
+    pub fn global_checksum(&self) -> u16 {
+        u16::from_be_bytes([
+            self.header_byte(0x14E).unwrap_or(0),
+            self.header_byte(0x14F).unwrap_or(0),
+        ])
+    }
+
+    pub fn compute_global_checksum(&self) -> u16 {
+        // Every byte of the ROM except the checksum itself
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|&(addr, _)| addr != 0x14E && addr != 0x14F)
+            .fold(0u16, |checksum, (_, &value)| {
+                checksum.wrapping_add(u16::from(value))
+            })
+    }
+
+    pub fn logo_valid(&self) -> bool {
+        self.0.get(0x104..0x134) == Some(&NINTENDO_LOGO[..])
+    }
+
+    pub fn info(&self) -> RomInfo {
+        RomInfo::new(self)
+    }
+
+    pub fn header_report(&self) -> HeaderReport {
+        HeaderReport {
+            logo_valid: self.logo_valid(),
+            header_checksum: self.header_checksum(),
+            computed_header_checksum: self.compute_header_checksum(),
+            global_checksum: self.global_checksum(),
+            computed_global_checksum: self.compute_global_checksum(),
+        }
     }
 
     pub fn read(&self, addr: u16) -> u8 {
         self.0[usize::from(addr)]
     }
+
+    pub fn read_bank(&self, bank: usize, addr: u16) -> u8 {
+        // Bank numbers larger than the ROM wrap around, as only the
+        // bank lines that are actually connected are decoded
+        let banks = (self.0.len() / 0x4000).max(1);
+        let offset = (bank % banks) * 0x4000;
+
+        self.0[offset + usize::from(addr & 0x3FFF)]
+    }
 }
 
-impl Into<Cartridge> for ROM {
-    fn into(self) -> Cartridge {
-        match self.cartridge_type() {
-            CartridgeType::ROMOnly => Cartridge::rom_only(self),
+impl TryFrom<ROM> for Cartridge {
+    type Error = RomError;
+
+    fn try_from(rom: ROM) -> Result<Self, Self::Error> {
+        let cartridge = match rom.cartridge_type()? {
+            CartridgeType::ROMOnly => Cartridge::rom_only(rom),
             CartridgeType::MBC1 => {
-                let ram_size = self.ram_size();
-                Cartridge::mbc1(self, ram_size)
+                let ram_size = rom.ram_size()?;
+                Cartridge::mbc1(rom, ram_size)
             }
-        }
+            CartridgeType::MBC2 => Cartridge::mbc2(rom),
+            CartridgeType::MBC3 => {
+                let ram_size = rom.ram_size()?;
+                let has_rtc = rom.has_timer();
+                Cartridge::mbc3(rom, ram_size, has_rtc)
+            }
+            CartridgeType::MBC5 => {
+                let ram_size = rom.ram_size()?;
+                let has_rumble = rom.has_rumble();
+                Cartridge::mbc5(rom, ram_size, has_rumble)
+            }
+        };
+
+        Ok(cartridge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with_header(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let mut bytes = vec![0; 32768 << rom_size];
+        bytes[0x147] = cartridge_type;
+        bytes[0x148] = rom_size;
+        bytes[0x149] = ram_size;
+        bytes
+    }
+
+    #[test]
+    fn it_should_verify_the_header() {
+        let mut bytes = rom_with_header(0x00, 0x00, 0x00);
+        bytes[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        bytes[0x134..0x13A].copy_from_slice(b"TETRIS");
+
+        let rom = ROM::from(bytes.clone());
+        bytes[0x14D] = rom.compute_header_checksum();
+
+        let rom = ROM::from(bytes.clone());
+        let [high, low] = rom.compute_global_checksum().to_be_bytes();
+        bytes[0x14E] = high;
+        bytes[0x14F] = low;
+
+        let report = ROM::from(bytes.clone()).header_report();
+        assert!(report.valid());
+
+        bytes[0x200] = 0xFF;
+
+        let report = ROM::from(bytes).header_report();
+        assert!(report.bootable());
+        assert!(!report.global_checksum_valid());
+    }
+
+    #[test]
+    fn it_should_parse_a_valid_rom() {
+        assert!(ROM::parse(rom_with_header(0x13, 0x01, 0x03)).is_ok());
+    }
+
+    #[test]
+    fn it_should_reject_a_truncated_file() {
+        assert_eq!(
+            Err(RomError::Truncated { len: 0x100 }),
+            ROM::parse(vec![0; 0x100]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn it_should_reject_unsupported_cartridge_types() {
+        assert_eq!(
+            Err(RomError::UnsupportedCartridgeType(0xFC)),
+            ROM::parse(rom_with_header(0xFC, 0x00, 0x00)).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn it_should_reject_invalid_ram_sizes() {
+        assert_eq!(
+            Err(RomError::InvalidRamSize(0x06)),
+            ROM::parse(rom_with_header(0x00, 0x00, 0x06)).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn it_should_reject_size_mismatches() {
+        let mut bytes = rom_with_header(0x01, 0x00, 0x00);
+        bytes[0x148] = 0x02;
+
+        assert_eq!(
+            Err(RomError::SizeMismatch {
+                expected: 131072,
+                actual: 32768
+            }),
+            ROM::parse(bytes).map(|_| ())
+        );
     }
 }
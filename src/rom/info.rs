@@ -0,0 +1,270 @@
+use std::fmt;
+
+use super::licensee::{new_licensee_name, old_licensee_name, USE_NEW_LICENSEE_CODE};
+use super::ROM;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+    Unknown(u8),
+}
+
+impl From<u8> for Destination {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => Destination::Japan,
+            0x01 => Destination::Overseas,
+            value => Destination::Unknown(value),
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Destination::Japan => write!(f, "Japan"),
+            Destination::Overseas => write!(f, "Overseas"),
+            Destination::Unknown(value) => write!(f, "Unknown ({:#04X})", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CGBMode {
+    DMGOnly,
+    CGBEnhanced,
+    CGBOnly,
+}
+
+impl From<u8> for CGBMode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x80 => CGBMode::CGBEnhanced,
+            0xC0 => CGBMode::CGBOnly,
+            _ => CGBMode::DMGOnly,
+        }
+    }
+}
+
+impl fmt::Display for CGBMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            CGBMode::DMGOnly => write!(f, "DMG only"),
+            CGBMode::CGBEnhanced => write!(f, "CGB enhanced"),
+            CGBMode::CGBOnly => write!(f, "CGB only"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CartridgeFeatures {
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+    pub sensor: bool,
+}
+
+impl CartridgeFeatures {
+    fn new(ram: bool, battery: bool, timer: bool, rumble: bool, sensor: bool) -> Self {
+        CartridgeFeatures {
+            ram,
+            battery,
+            timer,
+            rumble,
+            sensor,
+        }
+    }
+}
+
+/// Decodes the cartridge type byte at 0x0147 into the name of the mapper and
+/// the extra hardware on the cartridge.
+pub fn decode_cartridge_type(value: u8) -> Option<(&'static str, CartridgeFeatures)> {
+    let f = CartridgeFeatures::new;
+
+    let decoded = match value {
+        0x00 => ("ROM ONLY", f(false, false, false, false, false)),
+        0x01 => ("MBC1", f(false, false, false, false, false)),
+        0x02 => ("MBC1", f(true, false, false, false, false)),
+        0x03 => ("MBC1", f(true, true, false, false, false)),
+        // MBC2 has its RAM built into the mapper
+        0x05 => ("MBC2", f(true, false, false, false, false)),
+        0x06 => ("MBC2", f(true, true, false, false, false)),
+        0x08 => ("ROM", f(true, false, false, false, false)),
+        0x09 => ("ROM", f(true, true, false, false, false)),
+        0x0B => ("MMM01", f(false, false, false, false, false)),
+        0x0C => ("MMM01", f(true, false, false, false, false)),
+        0x0D => ("MMM01", f(true, true, false, false, false)),
+        0x0F => ("MBC3", f(false, true, true, false, false)),
+        0x10 => ("MBC3", f(true, true, true, false, false)),
+        0x11 => ("MBC3", f(false, false, false, false, false)),
+        0x12 => ("MBC3", f(true, false, false, false, false)),
+        0x13 => ("MBC3", f(true, true, false, false, false)),
+        0x19 => ("MBC5", f(false, false, false, false, false)),
+        0x1A => ("MBC5", f(true, false, false, false, false)),
+        0x1B => ("MBC5", f(true, true, false, false, false)),
+        0x1C => ("MBC5", f(false, false, false, true, false)),
+        0x1D => ("MBC5", f(true, false, false, true, false)),
+        0x1E => ("MBC5", f(true, true, false, true, false)),
+        0x20 => ("MBC6", f(true, true, false, false, false)),
+        0x22 => ("MBC7", f(true, true, false, true, true)),
+        0xFC => ("POCKET CAMERA", f(true, true, false, false, false)),
+        0xFD => ("BANDAI TAMA5", f(true, true, true, false, false)),
+        0xFE => ("HuC3", f(true, true, true, false, false)),
+        0xFF => ("HuC1", f(true, true, false, false, false)),
+        _ => return None,
+    };
+
+    Some(decoded)
+}
+
+pub struct RomInfo {
+    pub title: String,
+    pub manufacturer_code: String,
+    pub publisher: Option<&'static str>,
+    pub licensee_code: String,
+    pub cartridge_type: u8,
+    pub mapper: Option<&'static str>,
+    pub features: CartridgeFeatures,
+    pub rom_size: Option<usize>,
+    pub ram_size: Option<usize>,
+    pub destination: Destination,
+    pub cgb_mode: CGBMode,
+    pub sgb_support: bool,
+    pub mask_rom_version: u8,
+}
+
+impl RomInfo {
+    pub fn new(rom: &ROM) -> Self {
+        let (publisher, licensee_code) = match rom.old_licensee_code() {
+            USE_NEW_LICENSEE_CODE => {
+                let code = rom.new_licensee_code();
+                (new_licensee_name(&code), code)
+            }
+            code => (old_licensee_name(code), format!("{:02X}", code)),
+        };
+
+        let cartridge_type = rom.cartridge_type_code();
+        let (mapper, features) = match decode_cartridge_type(cartridge_type) {
+            Some((mapper, features)) => (Some(mapper), features),
+            None => (None, CartridgeFeatures::default()),
+        };
+
+        RomInfo {
+            title: rom.title(),
+            manufacturer_code: rom.manufacturer_code(),
+            publisher,
+            licensee_code,
+            cartridge_type,
+            mapper,
+            features,
+            rom_size: rom.rom_size().ok(),
+            ram_size: rom.ram_size().ok(),
+            destination: Destination::from(rom.destination_code()),
+            cgb_mode: CGBMode::from(rom.cgb_flag()),
+            // The SGB functions are only enabled if the old licensee code is also 0x33
+            sgb_support: rom.sgb_flag() == 0x03 && rom.old_licensee_code() == USE_NEW_LICENSEE_CODE,
+            mask_rom_version: rom.mask_rom_version(),
+        }
+    }
+
+    pub fn cartridge_type_name(&self) -> String {
+        let mapper = match self.mapper {
+            Some(mapper) => mapper,
+            None => return format!("Unknown ({:#04X})", self.cartridge_type),
+        };
+
+        let extras = [
+            (self.features.timer, "TIMER"),
+            (self.features.sensor, "SENSOR"),
+            (self.features.rumble, "RUMBLE"),
+            // MBC2's RAM is part of the mapper so the header name leaves it out
+            (
+                self.features.ram && !matches!(self.cartridge_type, 0x05 | 0x06),
+                "RAM",
+            ),
+            (self.features.battery, "BATTERY"),
+        ];
+
+        extras
+            .iter()
+            .filter(|(present, _)| *present)
+            .fold(String::from(mapper), |name, (_, extra)| name + "+" + extra)
+    }
+}
+
+fn format_size(size: Option<usize>) -> String {
+    match size {
+        Some(0) => String::from("None"),
+        Some(size) => format!("{} KiB", size / 1024),
+        None => String::from("Unknown"),
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Title: {}", self.title)?;
+        writeln!(
+            f,
+            "Publisher: {} ({})",
+            self.publisher.unwrap_or("Unknown"),
+            self.licensee_code
+        )?;
+        writeln!(f, "Cartridge: {}", self.cartridge_type_name())?;
+        writeln!(f, "ROM size: {}", format_size(self.rom_size))?;
+        writeln!(f, "RAM size: {}", format_size(self.ram_size))?;
+        writeln!(f, "Destination: {}", self.destination)?;
+        writeln!(f, "CGB: {}", self.cgb_mode)?;
+        writeln!(f, "SGB: {}", if self.sgb_support { "Yes" } else { "No" })?;
+        write!(f, "Version: {}", self.mask_rom_version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with(bytes: &[(usize, u8)]) -> ROM {
+        let mut data = vec![0; 32768];
+
+        for &(addr, value) in bytes {
+            data[addr] = value;
+        }
+
+        ROM::from(data)
+    }
+
+    #[test]
+    fn it_should_use_the_new_licensee_code_when_told_to() {
+        let info = RomInfo::new(&rom_with(&[
+            (0x144, b'0'),
+            (0x145, b'1'),
+            (0x146, 0x03),
+            (0x14B, 0x33),
+        ]));
+
+        assert_eq!(Some("Nintendo R&D1"), info.publisher);
+        assert!(info.sgb_support);
+    }
+
+    #[test]
+    fn it_should_ignore_the_sgb_flag_with_an_old_licensee_code() {
+        let info = RomInfo::new(&rom_with(&[(0x146, 0x03), (0x14B, 0x01)]));
+
+        assert_eq!(Some("Nintendo"), info.publisher);
+        assert!(!info.sgb_support);
+    }
+
+    #[test]
+    fn it_should_name_cartridge_types_like_the_header() {
+        let info = RomInfo::new(&rom_with(&[(0x147, 0x10)]));
+        assert_eq!("MBC3+TIMER+RAM+BATTERY", info.cartridge_type_name());
+
+        let info = RomInfo::new(&rom_with(&[(0x147, 0x22)]));
+        assert_eq!("MBC7+SENSOR+RUMBLE+RAM+BATTERY", info.cartridge_type_name());
+
+        let info = RomInfo::new(&rom_with(&[(0x147, 0x06)]));
+        assert_eq!("MBC2+BATTERY", info.cartridge_type_name());
+    }
+}
@@ -4,6 +4,8 @@ use core::cell::RefCell;
 use std::fmt;
 
 use super::hal::HAL;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::Model;
 
 pub enum ShiftClock {
     External,
@@ -61,6 +63,19 @@ impl Serial {
         }
     }
 
+    pub fn post_boot(model: Model, hal: Rc<RefCell<dyn HAL>>) -> Self {
+        // The colour models have an extra clock speed bit that reads back as set
+        let sc = match model {
+            Model::CGB | Model::AGB => 0x7F,
+            _ => 0x7E,
+        };
+
+        Serial {
+            sc: SC(sc),
+            ..Serial::new(hal)
+        }
+    }
+
     pub fn sb(&self) -> u8 {
         self.sb
     }
@@ -77,6 +92,18 @@ impl Serial {
         self.sc = SC(value);
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.sb);
+        state.write_u8(self.sc.0);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.sb = state.read_u8()?;
+        self.sc = SC(state.read_u8()?);
+
+        Ok(())
+    }
+
     pub fn tick_m_cycle(&mut self) -> bool {
         if self.sc.transfer_start() {
             self.sb = self.hal.borrow_mut().serial_callback(self.sb);
@@ -0,0 +1,215 @@
+use alloc::vec::Vec;
+use std::convert::TryInto;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"GBST";
+
+/// Bumped whenever the layout changes. Loading checks the version so that
+/// fields added in later versions can fall back to a default when loading
+/// an older state.
+pub const STATE_VERSION: u32 = 8;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum StateError {
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    WrongRom,
+    UnexpectedEnd,
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            StateError::InvalidMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is newer than the supported version {}",
+                version, STATE_VERSION
+            ),
+            StateError::WrongRom => write!(f, "save state was created with a different ROM"),
+            StateError::UnexpectedEnd => write!(f, "save state is truncated"),
+            StateError::InvalidValue(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut writer = StateWriter { data: Vec::new() };
+
+        writer.data.extend_from_slice(MAGIC);
+        writer.write_u32(STATE_VERSION);
+
+        writer
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a length prefixed block of bytes.
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.write_u32(value.len() as u32);
+        self.data.extend_from_slice(value);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    version: u32,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, StateError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+
+        let mut reader = StateReader {
+            data: &data[MAGIC.len()..],
+            version: 0,
+        };
+
+        let version = reader.read_u32()?;
+
+        if version == 0 || version > STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        reader.version = version;
+
+        Ok(reader)
+    }
+
+    /// The version of the state being read, which may be older than `STATE_VERSION`.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+
+        Ok(taken)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue("flag")),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    /// Reads a length prefixed block of bytes that must exactly fill `value`.
+    pub fn read_bytes_into(
+        &mut self,
+        value: &mut [u8],
+        field: &'static str,
+    ) -> Result<(), StateError> {
+        let bytes = self.read_bytes()?;
+
+        if bytes.len() != value.len() {
+            return Err(StateError::InvalidValue(field));
+        }
+
+        value.copy_from_slice(bytes);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_round_trip_values() {
+        let mut writer = StateWriter::new();
+        writer.write_u8(0x42);
+        writer.write_bool(true);
+        writer.write_u16(0x1234);
+        writer.write_u32(0xDEADBEEF);
+        writer.write_u64(0x0123_4567_89AB_CDEF);
+        writer.write_bytes(&[1, 2, 3]);
+
+        let data = writer.into_bytes();
+        let mut reader = StateReader::new(&data).unwrap();
+
+        assert_eq!(STATE_VERSION, reader.version());
+        assert_eq!(Ok(0x42), reader.read_u8());
+        assert_eq!(Ok(true), reader.read_bool());
+        assert_eq!(Ok(0x1234), reader.read_u16());
+        assert_eq!(Ok(0xDEADBEEF), reader.read_u32());
+        assert_eq!(Ok(0x0123_4567_89AB_CDEF), reader.read_u64());
+
+        let mut bytes = [0; 3];
+        assert_eq!(Ok(()), reader.read_bytes_into(&mut bytes, "bytes"));
+        assert_eq!([1, 2, 3], bytes);
+
+        assert_eq!(Err(StateError::UnexpectedEnd), reader.read_u8());
+    }
+
+    #[test]
+    fn it_should_reject_newer_versions() {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+
+        assert_eq!(
+            Err(StateError::UnsupportedVersion(STATE_VERSION + 1)),
+            StateReader::new(&data).map(|_| ())
+        );
+    }
+}
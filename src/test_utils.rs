@@ -0,0 +1,37 @@
+use alloc::vec::Vec;
+
+use crate::hal::{Joypad, HAL};
+
+/// A HAL with nothing attached, no buttons are pressed and nothing answers
+/// on the serial port.
+pub struct NullHAL;
+
+impl HAL for NullHAL {
+    fn is_joypad_pressed(&self, _: Joypad) -> bool {
+        false
+    }
+
+    fn serial_callback(&mut self, _: u8) -> u8 {
+        0xFF
+    }
+}
+
+/// A GBS header with the data loaded at `load_address`, init at 0x0410,
+/// play at 0x0420 and the timer set to call play.
+pub fn gbs_header(songs: u8, load_address: u16) -> Vec<u8> {
+    let mut bytes = vec![0; 0x70];
+    bytes[..3].copy_from_slice(b"GBS");
+    bytes[0x03] = 1;
+    bytes[0x04] = songs;
+    bytes[0x05] = 2;
+    bytes[0x06..0x08].copy_from_slice(&load_address.to_le_bytes());
+    bytes[0x08..0x0A].copy_from_slice(&0x0410u16.to_le_bytes());
+    bytes[0x0A..0x0C].copy_from_slice(&0x0420u16.to_le_bytes());
+    bytes[0x0C..0x0E].copy_from_slice(&0xDFFFu16.to_le_bytes());
+    bytes[0x0E] = 0xC0;
+    bytes[0x0F] = 0x04;
+    bytes[0x10..0x15].copy_from_slice(b"TITLE");
+    bytes[0x30..0x36].copy_from_slice(b"AUTHOR");
+
+    bytes
+}
@@ -1,6 +1,9 @@
 use bitfield::bitfield;
 use std::fmt;
 
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::Model;
+
 #[derive(Debug)]
 pub enum Frequency {
     _4096,
@@ -49,6 +52,7 @@ bitfield! {
     //impl Debug;
     u8;
     div, _: 15, 8;
+    frame_sequencer, _: 12;
 }
 
 bitfield! {
@@ -88,10 +92,67 @@ impl Timer {
         }
     }
 
+    /// The internal counter after each model's boot ROM has finished. Only the
+    /// upper byte is visible through DIV, the lower byte is the phase of the
+    /// counter which affects when TIMA first increments.
+    pub fn post_boot(model: Model) -> Self {
+        let counter = match model {
+            Model::DMG0 => 0x1830,
+            Model::DMG | Model::MGB => 0xABCC,
+            Model::SGB | Model::SGB2 => 0xD858,
+            Model::CGB | Model::AGB => 0x267C,
+        };
+
+        Timer {
+            counter: Counter(counter),
+            ..Timer::new()
+        }
+    }
+
+    pub fn power_on() -> Self {
+        Timer {
+            counter: Counter(0x0000),
+            ..Timer::new()
+        }
+    }
+
     pub fn div(&self) -> u8 {
         self.counter.div()
     }
 
+    /// Bit 4 of DIV, which clocks the APU's frame sequencer at 512 Hz.
+    pub fn frame_sequencer_bit(&self) -> bool {
+        self.counter.frame_sequencer()
+    }
+
+    /// Restores the registers without the side effects of writing to them, only
+    /// the upper byte of the internal counter is known.
+    pub fn restore_registers(&mut self, div: u8, tima: u8, tma: u8, tac: u8) {
+        self.counter = Counter(u16::from_be_bytes([div, 0]));
+        self.tima = tima;
+        self.tma = tma;
+        self.tac = TAC(tac);
+        self.overflow = false;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.counter.0);
+        state.write_u8(self.tac.0);
+        state.write_u8(self.tima);
+        state.write_u8(self.tma);
+        state.write_bool(self.overflow);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.counter = Counter(state.read_u16()?);
+        self.tac = TAC(state.read_u8()?);
+        self.tima = state.read_u8()?;
+        self.tma = state.read_u8()?;
+        self.overflow = state.read_bool()?;
+
+        Ok(())
+    }
+
     pub fn tac(&self) -> TAC {
         self.tac.clone()
     }
@@ -1,4 +1,3 @@
-use gb::Color;
 use gb::Gameboy;
 use gb::Joypad;
 use gb::HAL;
@@ -17,8 +16,6 @@ impl HAL for TestHAL {
         false
     }
 
-    fn put_pixel(&mut self, _: usize, _: usize, _: Color) {}
-
     fn serial_callback(&mut self, value: u8) -> u8 {
         print!("{}", value as char);
 
@@ -32,11 +29,11 @@ impl HAL for TestHAL {
 }
 
 fn run_test<P: AsRef<Path>>(path: P) -> String {
-    let rom = ROM::from(std::fs::read(path).unwrap());
+    let rom = ROM::parse(std::fs::read(path).unwrap()).unwrap();
     let hal = Rc::new(RefCell::new(TestHAL { output: Vec::new() }));
 
     {
-        let mut gameboy = Gameboy::new(rom, hal.clone());
+        let mut gameboy = Gameboy::new(rom, hal.clone()).unwrap();
 
         for _ in 0..30000000 {
             gameboy.step();